@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use crate::protocol::{ChatChannel, ChatMessage, PlayerName};
+
+/// Relays player chat and lets the server push system announcements
+/// ("2 players joined, waiting for 4", finish-line results) over
+/// `ChatChannel`. The client side just queues outgoing lines and logs
+/// whatever comes back; there's no chat UI in this tree yet to render the
+/// scrolling log/banner distinction `ChatMessage::overlay` exists for.
+pub struct ChatPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        if self.is_server {
+            app.add_systems(Update, relay_client_chat);
+        } else {
+            app.init_resource::<PendingChatMessages>();
+            app.add_systems(Update, (send_pending_chat, log_incoming_chat));
+        }
+    }
+}
+
+/// Player-authored chat lines waiting to go out over `ChatChannel`, drained
+/// by `send_pending_chat` each frame. A future text-entry UI can push onto
+/// this directly without touching the networking code.
+#[derive(Resource, Default)]
+pub struct PendingChatMessages(pub VecDeque<String>);
+
+fn send_pending_chat(
+    mut pending: ResMut<PendingChatMessages>,
+    mut connection_manager: ResMut<client::ConnectionManager>,
+) {
+    while let Some(body) = pending.0.pop_front() {
+        let message = ChatMessage {
+            sender: None,
+            body,
+            overlay: false,
+        };
+        let _ = connection_manager.send_message::<ChatChannel, ChatMessage>(&message);
+    }
+}
+
+fn log_incoming_chat(mut messages: EventReader<client::MessageEvent<ChatMessage>>) {
+    for event in messages.read() {
+        let message = event.message();
+        if message.overlay {
+            info!(body = %message.body, "system announcement");
+        } else {
+            info!(sender = ?message.sender, body = %message.body, "chat");
+        }
+    }
+}
+
+/// Re-broadcasts a client's chat line with its replicated `PlayerName` filled
+/// in, since the client doesn't know its own connection-scoped identity.
+fn relay_client_chat(
+    mut messages: EventReader<server::MessageEvent<ChatMessage>>,
+    mut connection_manager: ResMut<server::ConnectionManager>,
+    name_q: Query<(&PlayerName, &Replicated)>,
+) {
+    for event in messages.read() {
+        let client_id = event.from();
+        let sender = name_q
+            .iter()
+            .find(|(_, replicated)| replicated.from == Some(client_id))
+            .map(|(name, _)| name.0.clone());
+        let relayed = ChatMessage {
+            sender,
+            body: event.message().body.clone(),
+            overlay: false,
+        };
+        let _ = connection_manager
+            .send_message_to_target::<ChatChannel, ChatMessage>(&relayed, NetworkTarget::All);
+    }
+}
+
+/// Sends a server-authored announcement to every connected client: join/leave
+/// notices, finish-line results, countdown beats. `overlay` picks the
+/// transient on-screen banner over the scrolling chat log.
+pub fn broadcast_system_message(
+    connection_manager: &mut server::ConnectionManager,
+    body: impl Into<String>,
+    overlay: bool,
+) {
+    let message = ChatMessage {
+        sender: None,
+        body: body.into(),
+        overlay,
+    };
+    let _ = connection_manager
+        .send_message_to_target::<ChatChannel, ChatMessage>(&message, NetworkTarget::All);
+}
@@ -1,12 +1,21 @@
+mod chat;
 mod client_cam;
 mod client_grpc_server;
 mod client_grpc_service;
 mod config;
+mod handshake;
+mod lobby;
+mod mc_tables;
+mod mesh_export;
 mod player;
 mod player_input;
 mod protocol;
+mod rollback;
+mod sync_test;
+mod terrain;
 mod track_gen;
 mod track_mesh;
+mod vfx;
 mod world;
 
 use bevy_image_export::{GpuImageExportSource, ImageExport, ImageExportPlugin, ImageExportSource};
@@ -14,34 +23,45 @@ use lightyear::{
     prelude::client::{Predicted, Replicate},
     shared::replication::components::Controlled,
 };
-use std::{net::SocketAddr, sync::Arc, u32};
+use std::{collections::VecDeque, net::SocketAddr, sync::Arc, u32};
 
 use bevy::{
     asset::RenderAssetUsages,
+    core_pipeline::prepass::DepthPrepass,
     log::LogPlugin,
     prelude::*,
     render::{
         Render, RenderApp, RenderSet,
         render_asset::RenderAssets,
-        render_resource::{Extent3d, Maintain, MapMode, TextureUsages},
+        render_resource::{Extent3d, Maintain, MapMode, TextureFormat, TextureUsages},
         renderer::RenderDevice,
     },
     tasks::futures_lite,
     window::WindowResolution,
 };
+use bevy_rapier3d::prelude::*;
+use chat::ChatPlugin;
 use clap::Parser;
 use client::{Authentication, ClientCommands, ClientPlugins, IoConfig, NetConfig};
 use client_cam::{ClientCameraPlugin, DirectionalCamera};
-use client_grpc_server::marble::ResultEntry;
+use client_grpc_server::marble::{ChecksumEntry, ResetRequest, ResultEntry};
 use client_grpc_server::start_gprc_server;
-use config::shared_config;
-use lightyear::{connection::netcode::CONNECT_TOKEN_BYTES, prelude::*};
+use config::{TICK_RATE_HZ, shared_config};
+use handshake::{client_handshake, key_from_hex, read_encrypted};
+use lightyear::prelude::*;
+use lobby::AuthRequest;
 use player::PlayerPlugin;
 use player_input::PlayerInputPlugin;
-use protocol::{GameResult, Inputs, PlayerName, ProtocolPlugin, VelocityShare};
-use tokio::sync::{Mutex, oneshot};
+use protocol::{GameResult, Inputs, PlayerName, PlayerPosition, ProtocolPlugin, VelocityShare};
+use rollback::RollbackPlugin;
+use vfx::VfxPlugin;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, oneshot, watch};
 use world::{LowGpu, Seed, WorldPlugin};
 
+/// Number of `(tick, checksum)` pairs kept for `GetChecksumHistory`.
+const CHECKSUM_HISTORY_CAPACITY: usize = 256;
+
 #[derive(Parser)]
 struct ClientArgs {
     /// Ip address of the game server.
@@ -56,6 +76,22 @@ struct ClientArgs {
     /// Port used to start a grpc server and remote control this client.
     #[clap(long)]
     grpc_port: Option<u16>,
+    /// Also stream a per-pixel metric depth buffer alongside the color frame in `StateResponse`.
+    /// Only has an effect together with `--grpc-port`, since it adds an extra GPU readback.
+    #[clap(long)]
+    stream_depth: bool,
+    /// Number of observation/control endpoints to host in this one process, each with
+    /// its own lightyear connection, `Controlled` marble, offscreen camera and gRPC
+    /// server on consecutive ports starting at `--grpc-port`. Agent 0 runs on the main
+    /// thread (with the usual window unless `--grpc-port` is set); the rest each get
+    /// their own background thread and App instead of a whole separate OS process.
+    #[clap(long, default_value_t = 1)]
+    num_agents: u32,
+    /// Number of ticks to hold a submitted input before it is applied, so a remote
+    /// agent sees a fixed, known delay between reading a frame and its action taking
+    /// effect instead of whatever the network happened to do that frame.
+    #[clap(long, default_value_t = 0)]
+    input_delay: u16,
     /// Player name chosen for the game.
     #[clap(long, default_value_t = format!("Player1"))]
     name: String,
@@ -66,6 +102,14 @@ struct ClientArgs {
     #[clap(long)]
     low_gpu: bool,
 
+    /// Hex-encoded 32-byte X25519 public key identifying the auth server
+    /// this client expects to connect to (see `handshake.rs`). Must be the
+    /// public half of the server's `--static-secret-key`, or the public key it
+    /// printed at startup if none was given. Without the matching secret half, a MITM
+    /// can't derive the session key and the handshake fails closed.
+    #[clap(long)]
+    server_public_key: String,
+
     /// Verbose logging
     #[clap(long)]
     verbose: bool,
@@ -76,60 +120,141 @@ pub fn main() {
     let host = args.server;
     let auth_port = args.auth_port;
     let client_port: u16 = args.client_port;
-    let screen_mutex = Arc::new(Mutex::new(vec![]));
-    let current_input_mutex = Arc::new(Mutex::new(Inputs::None));
-    let finished = Arc::new(Mutex::new(false));
-    let linear_velocity = Arc::new(Mutex::new(Vec3::ZERO));
-    let angular_velocity = Arc::new(Mutex::new(Vec3::ZERO));
-    let results = Arc::new(Mutex::new(Vec::new()));
-
-    let _ = if let Some(grpc_port) = args.grpc_port {
-        start_gprc_server(
-            screen_mutex.clone(),
-            current_input_mutex.clone(),
-            finished.clone(),
-            linear_velocity.clone(),
-            angular_velocity.clone(),
-            results.clone(),
-            grpc_port,
-        )
-    } else {
-        std::thread::spawn(|| {})
-    };
+    let num_agents = args.num_agents.max(1);
+
+    let server_public_key = key_from_hex(&args.server_public_key)
+        .unwrap_or_else(|e| panic!("invalid --server-public-key: {e}"));
+
+    // Each agent gets its own `AgentChannels`, its own gRPC endpoint, AND
+    // (below) its own lightyear connection/`Controlled` marble: one shared
+    // connection can only ever replicate one marble per client id, so
+    // `--num-agents N` spawning N real, independently-controlled balls
+    // means opening N connections, not N cameras onto the same one.
+    let mut agents = Vec::with_capacity(num_agents as usize);
+    for i in 0..num_agents {
+        let (tick_tx, tick_rx) = watch::channel(0u32);
+        let agent = AgentChannels {
+            screen: Arc::new(Mutex::new(vec![])),
+            current_input: Arc::new(Mutex::new(Inputs::None)),
+            finished: Arc::new(Mutex::new(false)),
+            linear_velocity: Arc::new(Mutex::new(Vec3::ZERO)),
+            angular_velocity: Arc::new(Mutex::new(Vec3::ZERO)),
+            results: Arc::new(Mutex::new(Vec::new())),
+            depth: Arc::new(Mutex::new(DepthFrame::default())),
+            tick_tx,
+            checksum_history: Arc::new(Mutex::new(VecDeque::with_capacity(
+                CHECKSUM_HISTORY_CAPACITY,
+            ))),
+            pending_reset: Arc::new(Mutex::new(None)),
+        };
+
+        if let Some(grpc_port) = args.grpc_port {
+            let _ = start_gprc_server(
+                agent.screen.clone(),
+                agent.current_input.clone(),
+                agent.finished.clone(),
+                agent.linear_velocity.clone(),
+                agent.angular_velocity.clone(),
+                agent.results.clone(),
+                agent.depth.clone(),
+                tick_rx,
+                agent.checksum_history.clone(),
+                agent.pending_reset.clone(),
+                args.input_delay as u32,
+                grpc_port + i as u16,
+            );
+        }
+        agents.push(agent);
+    }
+
+    // Agents 1..N each run their own full client App (own connection, own
+    // physics, own `RollbackBall`) on a background thread, sharing only the
+    // OS process with agent 0. They're always headless/grpc-driven, since
+    // there's no keyboard to share between them. Each App still creates its
+    // own windowless render device, so this assumes a platform that's fine
+    // spinning up more than one of those outside the main thread (true of
+    // the Linux/Vulkan training boxes this mode targets).
+    let mut agent_threads = Vec::new();
+    for (i, agent) in agents.iter().enumerate().skip(1) {
+        let plugin = MyClientPlugin {
+            auth_addr: format!("{host}:{auth_port}").parse().unwrap(),
+            client_addr: format!("0.0.0.0:{}", client_port + i as u16).parse().unwrap(),
+            server_public_key,
+            agent: agent.clone(),
+            grpc: true,
+            name: format!("{}-agent{i}", args.name),
+            stream_depth: args.stream_depth,
+            input_delay: args.input_delay,
+            seed: args.seed,
+            low_gpu: args.low_gpu,
+            verbose: false,
+        };
+        agent_threads.push(std::thread::spawn(move || {
+            let mut app = App::new();
+            app.add_plugins(plugin);
+            app.run();
+        }));
+    }
 
     let mut app = App::new();
     app.add_plugins(MyClientPlugin {
         auth_addr: format!("{host}:{auth_port}").parse().unwrap(),
         client_addr: format!("0.0.0.0:{client_port}").parse().unwrap(),
-        screen: screen_mutex,
-        current_input: current_input_mutex,
+        server_public_key,
+        agent: agents[0].clone(),
         grpc: args.grpc_port.is_some(),
         name: args.name,
-        finished,
-        linear_velocity,
-        angular_velocity,
-        results,
+        stream_depth: args.stream_depth,
+        input_delay: args.input_delay,
         seed: args.seed,
         low_gpu: args.low_gpu,
         verbose: args.verbose,
     });
     app.run();
-    // server_thread.join().unwrap();
+    for thread in agent_threads {
+        let _ = thread.join();
+    }
+}
+
+/// A readback of the camera's depth attachment, converted from clip-space depth to
+/// per-pixel metric distance in meters (row-major, f32 little-endian).
+#[derive(Default, Clone)]
+pub struct DepthFrame {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Resource)]
 pub struct MyPlayerName(pub String, pub bool);
-struct MyClientPlugin {
-    auth_addr: SocketAddr,
-    client_addr: SocketAddr,
 
-    grpc: bool,
+/// One observation/control endpoint's worth of shared state: its own gRPC
+/// server, screen/depth buffers and episode bookkeeping. `--num-agents N`
+/// spawns `N` of these, each driving its own `Controlled` marble over its
+/// own lightyear connection (see `main`).
+#[derive(Clone)]
+pub(crate) struct AgentChannels {
     screen: Arc<Mutex<Vec<u8>>>,
-    current_input: Arc<Mutex<Inputs>>,
+    pub(crate) current_input: Arc<Mutex<Inputs>>,
     finished: Arc<Mutex<bool>>,
     linear_velocity: Arc<Mutex<bevy::math::Vec3>>,
     angular_velocity: Arc<Mutex<bevy::math::Vec3>>,
     results: Arc<Mutex<Vec<ResultEntry>>>,
+    depth: Arc<Mutex<DepthFrame>>,
+    tick_tx: watch::Sender<u32>,
+    checksum_history: Arc<Mutex<VecDeque<ChecksumEntry>>>,
+    pending_reset: Arc<Mutex<Option<ResetRequest>>>,
+}
+
+struct MyClientPlugin {
+    auth_addr: SocketAddr,
+    client_addr: SocketAddr,
+    server_public_key: [u8; 32],
+
+    grpc: bool,
+    agent: AgentChannels,
+    stream_depth: bool,
+    input_delay: u16,
 
     name: String,
     seed: u32,
@@ -138,15 +263,23 @@ struct MyClientPlugin {
     verbose: bool,
 }
 
-#[derive(Resource)]
-struct ControlViaGrpc {
-    screen: Arc<Mutex<Vec<u8>>>,
-    current_input: Arc<Mutex<Inputs>>,
-    finished: Arc<Mutex<bool>>,
-    linear_velocity: Arc<Mutex<bevy::math::Vec3>>,
-    angular_velocity: Arc<Mutex<bevy::math::Vec3>>,
-    results: Arc<Mutex<Vec<ResultEntry>>>,
-    enabled: bool,
+/// This App's one gRPC-controlled agent, read by `player_input::handle_input`
+/// to drive the local `Controlled` marble instead of the keyboard, and by the
+/// `sync_*_grpc` systems to report that marble's state back out. Each agent
+/// runs in its own App (see `main`), so there's exactly one of these per App.
+#[derive(Resource, Clone)]
+pub(crate) struct ControlViaGrpc {
+    pub(crate) agent: AgentChannels,
+    stream_depth: bool,
+    pub(crate) enabled: bool,
+}
+
+/// Marks the `ImageExport` bound to the depth camera's readback buffer, so
+/// `sync_depth_grpc` can tell it apart from the color export.
+#[derive(Component)]
+struct DepthExportTarget {
+    near: f32,
+    far: f32,
 }
 
 impl Plugin for MyClientPlugin {
@@ -193,45 +326,66 @@ impl Plugin for MyClientPlugin {
         app.insert_resource(LowGpu(self.low_gpu));
         app.add_systems(
             Update,
-            (attach_name, sync_finished_grpc, sync_velocities_grpc),
+            (
+                attach_name,
+                sync_finished_grpc,
+                sync_velocities_grpc,
+                apply_pending_reset_grpc,
+            ),
         );
+        app.add_systems(FixedUpdate, (sync_tick_grpc, sync_checksum_grpc).chain());
         app.insert_resource(ControlViaGrpc {
-            screen: self.screen.clone(),
-            current_input: self.current_input.clone(),
+            agent: self.agent.clone(),
             enabled: self.grpc,
-            finished: self.finished.clone(),
-            linear_velocity: self.linear_velocity.clone(),
-            angular_velocity: self.angular_velocity.clone(),
-            results: self.results.clone(),
+            stream_depth: self.stream_depth,
         });
         let render_app = app.sub_app_mut(RenderApp);
 
         render_app.insert_resource(ControlViaGrpc {
-            screen: self.screen.clone(),
-            current_input: self.current_input.clone(),
+            agent: self.agent.clone(),
             enabled: self.grpc,
-            finished: self.finished.clone(),
-            linear_velocity: self.linear_velocity.clone(),
-            angular_velocity: self.angular_velocity.clone(),
-            results: self.results.clone(),
+            stream_depth: self.stream_depth,
         });
         render_app.add_systems(
             Render,
-            sync_screen_grpc
+            (sync_screen_grpc, sync_depth_grpc)
                 .after(RenderSet::Render)
                 .before(RenderSet::Cleanup),
         );
-        app.add_plugins(build_client_plugin(self.auth_addr, self.client_addr));
+        app.add_plugins(build_client_plugin(
+            self.auth_addr,
+            self.client_addr,
+            self.server_public_key,
+        ));
         app.add_plugins(ProtocolPlugin);
-        app.add_plugins(PlayerInputPlugin);
-        app.add_plugins(WorldPlugin { physics: false });
+        app.add_plugins(ChatPlugin { is_server: false });
+        app.add_plugins(PlayerInputPlugin {
+            input_delay: self.input_delay,
+        });
+        // The track needs colliders client-side now that the local ball is
+        // simulated here too (see `RollbackPlugin`), not just interpolated
+        // from replicated positions.
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule());
+        app.add_plugins(WorldPlugin {
+            physics: true,
+            export_track_dir: None,
+            respawn_fall_y: -25.0,
+            respawn_stuck_secs: 5.0,
+            respawn_penalty_secs: 3.0,
+            fixed_hz: TICK_RATE_HZ,
+        });
         app.add_plugins(ClientCameraPlugin);
 
         app.add_plugins(PlayerPlugin {
             physics: false,
             player_count: 0,
             max_game_seconds: u32::MAX,
+            sync_test: false,
         });
+        app.add_plugins(RollbackPlugin {
+            prediction_window: 12,
+        });
+        app.add_plugins(VfxPlugin);
         app.add_systems(Startup, connect_client);
     }
 }
@@ -248,6 +402,8 @@ fn connect_client(
             height: 720,
             ..Default::default()
         };
+        // This App hosts exactly one agent's connection/marble (see `main`),
+        // so it needs exactly one offscreen camera/`ImageExport` pair.
         let mut image = Image::new_fill(
             size,
             bevy::render::render_resource::TextureDimension::D2,
@@ -272,6 +428,42 @@ fn connect_client(
             },
         ));
         commands.spawn(ImageExport(export_sources.add(image_handle)));
+
+        if grpc.stream_depth {
+            let mut depth_image = Image::new_fill(
+                size,
+                bevy::render::render_resource::TextureDimension::D2,
+                &[0, 0, 0, 0],
+                TextureFormat::Depth32Float,
+                RenderAssetUsages::default(),
+            );
+            depth_image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_SRC;
+            let depth_image_handle = images.add(depth_image);
+            let projection = Projection::Perspective(PerspectiveProjection::default());
+            let (near, far) = match &projection {
+                Projection::Perspective(p) => (p.near, p.far),
+                _ => (0.1, 1000.0),
+            };
+
+            commands.spawn((
+                Camera3d::default(),
+                projection,
+                Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
+                DirectionalCamera::default(),
+                DepthPrepass,
+                Camera {
+                    target: bevy::render::camera::RenderTarget::Image(depth_image_handle.clone()),
+                    ..Default::default()
+                },
+            ));
+            commands.spawn((
+                ImageExport(export_sources.add(depth_image_handle)),
+                DepthExportTarget { near, far },
+            ));
+        }
     } else {
         commands.spawn((
             Camera3d::default(),
@@ -288,33 +480,107 @@ fn sync_velocities_grpc(
 ) {
     let mut c = 0;
     for player in player_q.iter() {
+        let agent = &grpc.agent;
         futures_lite::future::block_on(async {
-            let mut lin = grpc.linear_velocity.lock().await;
+            let mut lin = agent.linear_velocity.lock().await;
             *lin = player.linear;
         });
 
         futures_lite::future::block_on(async {
-            let mut ang = grpc.angular_velocity.lock().await;
+            let mut ang = agent.angular_velocity.lock().await;
             *ang = player.angular;
         });
         c += 1;
     }
+    // This agent's connection should only ever replicate its own one marble
+    // (see `main`), so this would indicate the server associating more than
+    // one `Controlled` entity with this client id. Report it instead of
+    // crashing the whole process -- the agent just ends up reporting
+    // whichever of the entities' velocities it happened to sync last.
     if c > 1 {
-        panic!()
+        warn!(count = c, "more than one Controlled marble reported velocities for this agent");
     }
 }
 
-fn sync_finished_grpc(grpc: Res<ControlViaGrpc>, finished: Query<&GameResult>) {
+fn sync_tick_grpc(grpc: Res<ControlViaGrpc>, tick_manager: Res<TickManager>) {
+    let tick = tick_manager.tick().0 as u32;
+    // `send_if_modified` keeps the watch channel from being marked "changed" every
+    // fixed-timestep tick if the tick number itself hasn't actually advanced yet.
+    grpc.agent.tick_tx.send_if_modified(|current| {
+        if *current != tick {
+            *current = tick;
+            true
+        } else {
+            false
+        }
+    });
+}
+
+/// Hashes the deterministic, fixed-point-quantized game state (position, rotation
+/// and velocities of the controlled marble) and stores it in the checksum ring
+/// buffer, keyed by tick. Lets an external harness compare two instances and find
+/// the earliest tick they diverged on.
+fn sync_checksum_grpc(
+    grpc: Res<ControlViaGrpc>,
+    tick_manager: Res<TickManager>,
+    player_q: Query<(&PlayerPosition, &VelocityShare), (With<Controlled>, Without<Predicted>)>,
+) {
     let mut c = 0;
-    for r in finished.iter() {
+    for (position, velocity) in player_q.iter() {
         c += 1;
+        let mut bytes = Vec::with_capacity(40);
+        for v in [position.0.x, position.0.y, position.0.z] {
+            bytes.extend_from_slice(&quantize(v).to_le_bytes());
+        }
+        for v in [position.1.x, position.1.y, position.1.z, position.1.w] {
+            bytes.extend_from_slice(&quantize(v).to_le_bytes());
+        }
+        for v in [velocity.linear.x, velocity.linear.y, velocity.linear.z] {
+            bytes.extend_from_slice(&quantize(v).to_le_bytes());
+        }
+        for v in [velocity.angular.x, velocity.angular.y, velocity.angular.z] {
+            bytes.extend_from_slice(&quantize(v).to_le_bytes());
+        }
+        let entry = ChecksumEntry {
+            tick: tick_manager.tick().0 as u64,
+            checksum: fnv1a_hash(&bytes),
+        };
+
         futures_lite::future::block_on(async {
-            let mut finished = grpc.finished.lock().await;
-            if !*finished {
-                *finished = true;
+            let mut history = grpc.agent.checksum_history.lock().await;
+            if history.len() == CHECKSUM_HISTORY_CAPACITY {
+                history.pop_front();
             }
+            history.push_back(entry);
         });
+    }
+    // Same caveat as `sync_velocities_grpc`: each matching entity already got
+    // its own entry pushed into `checksum_history` above, so more than one
+    // just means more than one checksum recorded for this tick instead of
+    // a crash.
+    if c > 1 {
+        warn!(count = c, "more than one Controlled marble reported checksums for this agent");
+    }
+}
+
+/// Quantizes a float to a fixed-point integer so the checksum is stable across
+/// platforms/compilers, where raw float bit patterns are not guaranteed to match.
+fn quantize(v: f32) -> i32 {
+    (v * 1000.0).round() as i32
+}
+
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
 
+fn sync_finished_grpc(grpc: Res<ControlViaGrpc>, finished: Query<&GameResult>) {
+    let mut c = 0;
+    for r in finished.iter() {
+        c += 1;
         let results: Vec<_> = r
             .players
             .iter()
@@ -336,7 +602,14 @@ fn sync_finished_grpc(grpc: Res<ControlViaGrpc>, finished: Query<&GameResult>) {
             .collect();
 
         futures_lite::future::block_on(async {
-            let mut results_lock = grpc.results.lock().await;
+            let mut finished = grpc.agent.finished.lock().await;
+            if !*finished {
+                *finished = true;
+            }
+        });
+
+        futures_lite::future::block_on(async {
+            let mut results_lock = grpc.agent.results.lock().await;
             *results_lock = results;
         });
     }
@@ -347,10 +620,11 @@ fn sync_finished_grpc(grpc: Res<ControlViaGrpc>, finished: Query<&GameResult>) {
 
 fn sync_screen_grpc(
     grpc: Res<ControlViaGrpc>,
-    export_bundles: Query<&ImageExport>,
+    export_bundles: Query<&ImageExport, Without<DepthExportTarget>>,
     sources: Res<RenderAssets<GpuImageExportSource>>,
     render_device: Res<RenderDevice>,
 ) {
+    let agent = &grpc.agent;
     for export in &export_bundles {
         if let Some(gpu_source) = sources.get(&export.0) {
             let mut image_bytes = {
@@ -387,19 +661,98 @@ fn sync_screen_grpc(
                 image_bytes = unpadded_bytes;
             }
             futures_lite::future::block_on(async {
-                let mut l = grpc.screen.lock().await;
+                let mut l = agent.screen.lock().await;
                 *l = image_bytes
             });
         }
     }
 }
 
-fn build_client_plugin(auth_addr: SocketAddr, client_addr: SocketAddr) -> ClientPlugins {
+fn sync_depth_grpc(
+    grpc: Res<ControlViaGrpc>,
+    export_bundles: Query<(&ImageExport, &DepthExportTarget)>,
+    sources: Res<RenderAssets<GpuImageExportSource>>,
+    render_device: Res<RenderDevice>,
+) {
+    if !grpc.stream_depth {
+        return;
+    }
+    let agent = &grpc.agent;
+    for (export, depth_target) in &export_bundles {
+        if let Some(gpu_source) = sources.get(&export.0) {
+            let mut depth_bytes = {
+                let slice = gpu_source.buffer.slice(..);
+
+                {
+                    let (mapping_tx, mapping_rx) = oneshot::channel();
+
+                    render_device.map_buffer(&slice, MapMode::Read, move |res| {
+                        mapping_tx.send(res).unwrap();
+                    });
+
+                    render_device.poll(Maintain::Wait);
+                    futures_lite::future::block_on(mapping_rx).unwrap().unwrap();
+                }
+
+                slice.get_mapped_range().to_vec()
+            };
+
+            gpu_source.buffer.unmap();
+
+            let bytes_per_row = gpu_source.bytes_per_row as usize;
+            let padded_bytes_per_row = gpu_source.padded_bytes_per_row as usize;
+            let source_size = gpu_source.source_size;
+
+            if bytes_per_row != padded_bytes_per_row {
+                let mut unpadded_bytes =
+                    Vec::<u8>::with_capacity(source_size.height as usize * bytes_per_row);
+
+                for padded_row in depth_bytes.chunks(padded_bytes_per_row) {
+                    unpadded_bytes.extend_from_slice(&padded_row[..bytes_per_row]);
+                }
+
+                depth_bytes = unpadded_bytes;
+            }
+
+            let metric_depth = depth_bytes
+                .chunks_exact(4)
+                .flat_map(|c| {
+                    let ndc_depth = f32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+                    let distance =
+                        reversed_z_to_metric_distance(ndc_depth, depth_target.near, depth_target.far);
+                    distance.to_le_bytes()
+                })
+                .collect::<Vec<u8>>();
+
+            futures_lite::future::block_on(async {
+                let mut l = agent.depth.lock().await;
+                l.bytes = metric_depth;
+                l.width = source_size.width;
+                l.height = source_size.height;
+            });
+        }
+    }
+}
+
+/// Converts a reversed-infinite-Z perspective depth sample (as written by Bevy's depth
+/// prepass) into a metric distance from the camera, in meters.
+fn reversed_z_to_metric_distance(ndc_depth: f32, near: f32, far: f32) -> f32 {
+    if ndc_depth <= 0.0 {
+        return far;
+    }
+    (near / ndc_depth).min(far)
+}
+
+fn build_client_plugin(
+    auth_addr: SocketAddr,
+    client_addr: SocketAddr,
+    server_public_key: [u8; 32],
+) -> ClientPlugins {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
-    let connect_token = rt.block_on(get_connect_token(auth_addr));
+    let connect_token = rt.block_on(get_connect_token(auth_addr, server_public_key));
     let auth = Authentication::Token(connect_token);
     let io = IoConfig {
         transport: client::ClientTransport::UdpSocket(client_addr),
@@ -419,12 +772,49 @@ fn build_client_plugin(auth_addr: SocketAddr, client_addr: SocketAddr) -> Client
     ClientPlugins::new(config)
 }
 
-async fn get_connect_token(auth_addr: SocketAddr) -> ConnectToken {
-    let stream = tokio::net::TcpStream::connect(auth_addr).await.unwrap();
-    stream.readable().await.unwrap();
-    let mut buffer = [0u8; CONNECT_TOKEN_BYTES];
-    stream.try_read(&mut buffer).unwrap();
-    ConnectToken::try_from_bytes(&buffer).unwrap()
+async fn get_connect_token(auth_addr: SocketAddr, server_public_key: [u8; 32]) -> ConnectToken {
+    let mut stream = tokio::net::TcpStream::connect(auth_addr).await.unwrap();
+    stream
+        .write_all(&[AuthRequest::Token.to_byte()])
+        .await
+        .unwrap();
+    let session_key = client_handshake(&mut stream, server_public_key)
+        .await
+        .expect("auth handshake with server failed");
+    let token_bytes = read_encrypted(&mut stream, &session_key)
+        .await
+        .expect("failed to read encrypted connect token");
+    ConnectToken::try_from_bytes(&token_bytes).unwrap()
+}
+
+/// Consumes a pending `Reset` RPC: optionally re-seeds the world, despawns the
+/// controlled player, and clears episode-scoped state so `attach_name` spawns a
+/// fresh one at the track start next frame. Lets RL agents run `reset()` between
+/// episodes instead of killing and relaunching the client.
+fn apply_pending_reset_grpc(
+    grpc: Res<ControlViaGrpc>,
+    mut commands: Commands,
+    mut seed: ResMut<Seed>,
+    mut my_name: ResMut<MyPlayerName>,
+    controlled_q: Query<Entity, With<Controlled>>,
+) {
+    let pending = futures_lite::future::block_on(async {
+        grpc.agent.pending_reset.lock().await.take()
+    });
+    let Some(reset) = pending else {
+        return;
+    };
+    if let Some(new_seed) = reset.seed {
+        seed.0 = new_seed;
+    }
+    for entity in &controlled_q {
+        commands.entity(entity).despawn();
+    }
+    my_name.1 = false;
+    futures_lite::future::block_on(async {
+        *grpc.agent.finished.lock().await = false;
+        grpc.agent.results.lock().await.clear();
+    });
 }
 
 fn attach_name(mut my_name: ResMut<MyPlayerName>, mut commands: Commands) {
@@ -1,10 +1,13 @@
 use bevy::prelude::*;
 use lightyear::shared::replication::components::Controlled;
 
+use crate::protocol::Spectator;
+
 pub struct ClientCameraPlugin;
 impl Plugin for ClientCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Update, align_camera_with_movement);
+        app.add_systems(Update, (attach_spectator_camera, fly_spectator_camera));
     }
 }
 
@@ -79,3 +82,78 @@ fn align_camera_with_movement(
     // Store current position for next frame
     directional_camera.previous_position = current_position;
 }
+
+/// A camera a spectator can fly around freely instead of having it chase a
+/// ball, since a spectator's own entity has no `Transform`/physics of its own
+/// to follow.
+#[derive(Component)]
+pub struct FreeFlyCamera {
+    speed: f32,
+}
+
+impl Default for FreeFlyCamera {
+    fn default() -> Self {
+        Self { speed: 10.0 }
+    }
+}
+
+/// Gives a client its own free-fly camera the first time it shows up as a
+/// `Spectator`, instead of the ball-chasing `DirectionalCamera` every other
+/// controlled player gets from `connect_client`. A client is either chasing
+/// its own ball or spectating, never both, so this also despawns the default
+/// `DirectionalCamera` `connect_client` always spawns -- left alone, it kept
+/// sitting at its fixed startup transform rendering alongside the free-fly
+/// camera the spectator actually wants to use.
+fn attach_spectator_camera(
+    spectator_query: Query<Entity, (With<Spectator>, With<Controlled>, Without<FreeFlyCamera>)>,
+    existing_camera: Query<Entity, With<FreeFlyCamera>>,
+    directional_cameras: Query<Entity, With<DirectionalCamera>>,
+    mut commands: Commands,
+) {
+    if !existing_camera.is_empty() {
+        return;
+    }
+    for _ in &spectator_query {
+        for camera in &directional_cameras {
+            commands.entity(camera).despawn();
+        }
+        commands.spawn((
+            Camera3d::default(),
+            Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
+            FreeFlyCamera::default(),
+        ));
+    }
+}
+
+fn fly_spectator_camera(
+    time: Res<Time>,
+    keypress: Res<ButtonInput<KeyCode>>,
+    mut camera_query: Query<(&mut Transform, &FreeFlyCamera)>,
+) {
+    for (mut transform, camera) in &mut camera_query {
+        let mut movement = Vec3::ZERO;
+        let forward = transform.forward().as_vec3();
+        let right = transform.right().as_vec3();
+        if keypress.pressed(KeyCode::KeyW) {
+            movement += forward;
+        }
+        if keypress.pressed(KeyCode::KeyS) {
+            movement -= forward;
+        }
+        if keypress.pressed(KeyCode::KeyA) {
+            movement -= right;
+        }
+        if keypress.pressed(KeyCode::KeyD) {
+            movement += right;
+        }
+        if keypress.pressed(KeyCode::Space) {
+            movement += Vec3::Y;
+        }
+        if keypress.pressed(KeyCode::ShiftLeft) {
+            movement -= Vec3::Y;
+        }
+        if let Some(direction) = movement.try_normalize() {
+            transform.translation += direction * camera.speed * time.delta_secs();
+        }
+    }
+}
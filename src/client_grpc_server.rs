@@ -1,8 +1,10 @@
+use std::pin::Pin;
 use std::sync::Arc;
 use std::thread::JoinHandle;
 
 use bevy::math::Vec3;
-use tokio::sync::Mutex;
+use futures_core::Stream;
+use tokio::sync::{Mutex, watch};
 use tonic::{Request, Response, Status, transport::Server};
 
 // Import the generated proto code
@@ -13,10 +15,15 @@ pub mod marble {
 }
 
 use marble::marble_service_server::{MarbleService, MarbleServiceServer};
-use marble::{EmptyResponse, GetStateRequest, InputRequest, ResultEntry, StateResponse};
+use marble::{
+    ChecksumEntry, ChecksumHistoryRequest, ChecksumHistoryResponse, EmptyResponse,
+    GetStateRequest, InputRequest, ResetRequest, ResultEntry, StateResponse,
+};
 
+use crate::DepthFrame;
 use crate::client_grpc_service::GRPCService;
 use crate::protocol::Inputs;
+use std::collections::VecDeque;
 
 pub struct GRPCServer {
     pub service: GRPCService,
@@ -33,6 +40,26 @@ impl MarbleService for GRPCServer {
     async fn input(&self, r: Request<InputRequest>) -> Result<Response<EmptyResponse>, Status> {
         self.service.input(r.into_inner()).await
     }
+
+    type ObserveStreamStream = Pin<Box<dyn Stream<Item = Result<StateResponse, Status>> + Send>>;
+
+    async fn observe_stream(
+        &self,
+        _: Request<GetStateRequest>,
+    ) -> Result<Response<Self::ObserveStreamStream>, Status> {
+        Ok(Response::new(Box::pin(self.service.observe_stream())))
+    }
+
+    async fn get_checksum_history(
+        &self,
+        _: Request<ChecksumHistoryRequest>,
+    ) -> Result<Response<ChecksumHistoryResponse>, Status> {
+        self.service.get_checksum_history().await
+    }
+
+    async fn reset(&self, r: Request<ResetRequest>) -> Result<Response<EmptyResponse>, Status> {
+        self.service.reset(r.into_inner()).await
+    }
 }
 
 pub fn start_gprc_server(
@@ -42,6 +69,11 @@ pub fn start_gprc_server(
     linear_velocity: Arc<Mutex<Vec3>>,
     angular_velocity: Arc<Mutex<Vec3>>,
     results: Arc<Mutex<Vec<ResultEntry>>>,
+    depth: Arc<Mutex<DepthFrame>>,
+    tick: watch::Receiver<u32>,
+    checksum_history: Arc<Mutex<VecDeque<ChecksumEntry>>>,
+    pending_reset: Arc<Mutex<Option<ResetRequest>>>,
+    input_delay_ticks: u32,
     grpc_port: u16,
 ) -> JoinHandle<()> {
     std::thread::spawn(move || {
@@ -58,6 +90,12 @@ pub fn start_gprc_server(
                 linear_velocity,
                 angular_velocity,
                 results,
+                depth,
+                tick,
+                checksum_history,
+                pending_reset,
+                input_delay_ticks,
+                last_used: Arc::new(Mutex::new(std::time::Instant::now())),
             },
         };
         let reflection = tonic_reflection::server::Builder::configure()
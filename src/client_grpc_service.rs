@@ -1,14 +1,20 @@
-use std::{sync::Arc, time::Instant};
+use std::{collections::VecDeque, sync::Arc, time::Instant};
 
+use async_stream::stream;
 use bevy::math::Quat;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, watch};
 use tonic::{Response, Status};
 
 use crate::{
-    client_grpc_server::marble::{EmptyResponse, InputRequest, ResultEntry, StateResponse, Vec3},
+    DepthFrame,
+    client_grpc_server::marble::{
+        ChecksumEntry, ChecksumHistoryResponse, EmptyResponse, InputRequest, ResetRequest,
+        ResultEntry, StateResponse, Vec3,
+    },
     protocol::{Direction, Inputs},
 };
 
+#[derive(Clone)]
 pub struct GRPCService {
     pub screen: Arc<Mutex<Vec<u8>>>,
     pub current_input: Arc<Mutex<Inputs>>,
@@ -16,11 +22,30 @@ pub struct GRPCService {
     pub linear_velocity: Arc<Mutex<bevy::math::Vec3>>,
     pub angular_velocity: Arc<Mutex<bevy::math::Vec3>>,
     pub results: Arc<Mutex<Vec<ResultEntry>>>,
+    pub depth: Arc<Mutex<DepthFrame>>,
+    /// Notified once per simulation tick from the fixed-timestep schedule; also
+    /// carries the latest tick number for `get_state`/`observe_stream` to tag frames with.
+    pub tick: watch::Receiver<u32>,
+    /// Ring buffer of the last `N` `(tick, checksum)` pairs, for desync detection.
+    pub checksum_history: Arc<Mutex<VecDeque<ChecksumEntry>>>,
+    /// Set by `reset`, consumed by a Bevy system that respawns the controlled
+    /// player and optionally re-seeds the world.
+    pub pending_reset: Arc<Mutex<Option<ResetRequest>>>,
+    /// Number of ticks `--input-delay` holds an `InputRequest` before it is
+    /// applied. Constant for the lifetime of the client, so it's plain data
+    /// rather than another `Arc<Mutex<_>>` channel.
+    pub input_delay_ticks: u32,
     pub last_used: Arc<Mutex<Instant>>,
 }
 
 impl GRPCService {
     pub async fn get_state(&self) -> Result<Response<StateResponse>, Status> {
+        self.snapshot().await.map(Response::new)
+    }
+
+    /// Builds one `StateResponse` out of the current shared state. Shared by the
+    /// unary `get_state` RPC and the `observe_stream` server-streaming RPC.
+    async fn snapshot(&self) -> Result<StateResponse, Status> {
         {
             let mut n = self.last_used.lock().await;
             *n = Instant::now();
@@ -47,11 +72,20 @@ impl GRPCService {
             }
         };
         let results = { self.results.lock().await.clone() };
+        let depth = { self.depth.lock().await.clone() };
+        let tick = *self.tick.borrow() as u64;
+        let state_checksum = self
+            .checksum_history
+            .lock()
+            .await
+            .back()
+            .map(|e| e.checksum)
+            .unwrap_or_default();
         let relative = angular_velocity_relative_to_movement(
             bevy::math::Vec3::new(ang.x, ang.y, ang.z),
             bevy::math::Vec3::new(lin.x, lin.y, lin.z),
         );
-        Ok(Response::new(StateResponse {
+        Ok(StateResponse {
             screen: screen_copy,
             linear_velocity: Some(lin),
             angular_velocity: Some(ang),
@@ -62,7 +96,40 @@ impl GRPCService {
             }),
             finished,
             results,
-        }))
+            depth: depth.bytes,
+            depth_width: depth.width,
+            depth_height: depth.height,
+            tick,
+            state_checksum,
+            input_delay_ticks: self.input_delay_ticks,
+            prediction_window_ticks: self.input_delay_ticks,
+        })
+    }
+
+    pub async fn get_checksum_history(&self) -> Result<Response<ChecksumHistoryResponse>, Status> {
+        let entries = self.checksum_history.lock().await.iter().cloned().collect();
+        Ok(Response::new(ChecksumHistoryResponse { entries }))
+    }
+
+    pub async fn reset(&self, r: ResetRequest) -> Result<Response<EmptyResponse>, Status> {
+        let mut pending = self.pending_reset.lock().await;
+        *pending = Some(r);
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    /// Pushes one `StateResponse` every time the fixed-timestep schedule advances
+    /// the tick, instead of letting callers race the render/physics loop by polling.
+    pub fn observe_stream(&self) -> impl futures_core::Stream<Item = Result<StateResponse, Status>> + Send + 'static {
+        let service = self.clone();
+        let mut tick_rx = service.tick.clone();
+        stream! {
+            loop {
+                if tick_rx.changed().await.is_err() {
+                    break;
+                }
+                yield service.snapshot().await;
+            }
+        }
     }
 
     pub async fn input(&self, r: InputRequest) -> Result<Response<EmptyResponse>, Status> {
@@ -70,17 +137,27 @@ impl GRPCService {
             let mut n = self.last_used.lock().await;
             *n = Instant::now();
         }
-        let d = Direction {
-            forward: r.forward,
-            back: r.back,
-            left: r.left,
-            right: r.right,
-            reset: r.reset,
-        };
-        let i = if d.is_some() {
-            Inputs::Direction(d)
+        // Analog fields take priority over the boolean direction ones: a caller
+        // driving the marble with continuous control shouldn't also need to
+        // zero out `forward`/`back`/`left`/`right` on every request.
+        let i = if r.steer.is_some() || r.throttle.is_some() {
+            Inputs::Analog {
+                steer: r.steer.unwrap_or(0.0),
+                throttle: r.throttle.unwrap_or(0.0),
+            }
         } else {
-            Inputs::None
+            let d = Direction {
+                forward: r.forward,
+                back: r.back,
+                left: r.left,
+                right: r.right,
+                reset: r.reset,
+            };
+            if d.is_some() {
+                Inputs::Direction(d)
+            } else {
+                Inputs::None
+            }
         };
         let mut current = self.current_input.lock().await;
         *current = i;
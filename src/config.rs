@@ -2,11 +2,17 @@ use std::time::Duration;
 
 use lightyear::prelude::*;
 
+/// Ticks per second shared by the network protocol's `TickConfig` and
+/// `WorldPlugin`'s fixed physics step, so the two stay in lockstep instead of
+/// drifting apart and making replicated state look like it's constantly
+/// correcting itself.
+pub const TICK_RATE_HZ: f64 = 64.0;
+
 pub fn shared_config() -> SharedConfig {
     SharedConfig {
         server_replication_send_interval: Duration::from_millis(40),
         tick: TickConfig {
-            tick_duration: Duration::from_secs_f64(1.0 / 64.0),
+            tick_duration: Duration::from_secs_f64(1.0 / TICK_RATE_HZ),
         },
         mode: Mode::Separate,
     }
@@ -0,0 +1,159 @@
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, KeyInit},
+};
+use hkdf::Hkdf;
+use rand::{TryRngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret, SharedSecret};
+
+/// Generates a fresh X25519 static keypair for the auth server's long-term
+/// identity. The secret half must stay on the server (never compiled into or
+/// shipped with a client build); only `PublicKey::from(&secret).to_bytes()`
+/// should reach clients, e.g. via `--server-public-key`.
+pub fn generate_static_keypair() -> StaticSecret {
+    StaticSecret::random_from_rng(OsRng)
+}
+
+/// Hex-encodes a key for printing/passing on the command line.
+pub fn key_to_hex(key: &[u8; 32]) -> String {
+    key.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Parses a hex-encoded 32-byte key, as produced by `key_to_hex`.
+pub fn key_from_hex(s: &str) -> Result<[u8; 32], String> {
+    if s.len() != 64 {
+        return Err(format!("expected 64 hex chars, got {}", s.len()));
+    }
+    let mut key = [0u8; 32];
+    for (i, chunk) in key.iter_mut().zip(0..32) {
+        *i = u8::from_str_radix(&s[chunk * 2..chunk * 2 + 2], 16)
+            .map_err(|e| format!("invalid hex at byte {chunk}: {e}"))?;
+    }
+    Ok(key)
+}
+
+/// Runs the server side of the pre-`ConnectToken` handshake: exchanges
+/// ephemeral X25519 keys with the client, then mixes an ephemeral-ephemeral
+/// and a static-ephemeral ECDH into an HKDF to get a session key only a
+/// holder of `static_secret` could derive.
+pub async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    static_secret: &StaticSecret,
+) -> std::io::Result<[u8; 32]> {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+    let mut client_ephemeral_bytes = [0u8; 32];
+    stream.read_exact(&mut client_ephemeral_bytes).await?;
+    let client_ephemeral_public = PublicKey::from(client_ephemeral_bytes);
+
+    stream.write_all(ephemeral_public.as_bytes()).await?;
+
+    let ee = ephemeral_secret.diffie_hellman(&client_ephemeral_public);
+    let se = static_secret.diffie_hellman(&client_ephemeral_public);
+
+    Ok(derive_session_key(
+        &ee,
+        &se,
+        &client_ephemeral_public,
+        &ephemeral_public,
+    ))
+}
+
+/// Runs the client side of the handshake. The derived session key only
+/// matches the server's if `expected_server_public_key` is the real server's
+/// static key, since that's the only way the two sides' `se` terms agree --
+/// a MITM without the matching static secret ends up deriving a different
+/// key and the subsequent `ConnectToken` decryption simply fails.
+pub async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    expected_server_public_key: [u8; 32],
+) -> std::io::Result<[u8; 32]> {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+    stream.write_all(ephemeral_public.as_bytes()).await?;
+
+    let mut server_ephemeral_bytes = [0u8; 32];
+    stream.read_exact(&mut server_ephemeral_bytes).await?;
+    let server_ephemeral_public = PublicKey::from(server_ephemeral_bytes);
+
+    let ee = ephemeral_secret.diffie_hellman(&server_ephemeral_public);
+    let se = ephemeral_secret.diffie_hellman(&PublicKey::from(expected_server_public_key));
+
+    Ok(derive_session_key(
+        &ee,
+        &se,
+        &ephemeral_public,
+        &server_ephemeral_public,
+    ))
+}
+
+fn derive_session_key(
+    ee: &SharedSecret,
+    se: &SharedSecret,
+    client_ephemeral_public: &PublicKey,
+    server_ephemeral_public: &PublicKey,
+) -> [u8; 32] {
+    let mut transcript = Sha256::new();
+    transcript.update(client_ephemeral_public.as_bytes());
+    transcript.update(server_ephemeral_public.as_bytes());
+    let transcript_hash = transcript.finalize();
+
+    let mut ikm = Vec::with_capacity(64);
+    ikm.extend_from_slice(ee.as_bytes());
+    ikm.extend_from_slice(se.as_bytes());
+
+    let hk = Hkdf::<Sha256>::new(Some(&transcript_hash), &ikm);
+    let mut session_key = [0u8; 32];
+    hk.expand(b"omnia-auth-session-key", &mut session_key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    session_key
+}
+
+/// Encrypts `plaintext` with `session_key` under a fresh nonce and writes it
+/// length-prefixed: `len(u32 LE) || nonce(12) || ciphertext+tag`.
+pub async fn write_encrypted<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    session_key: &[u8; 32],
+    plaintext: &[u8],
+) -> std::io::Result<()> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(session_key));
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.try_fill_bytes(&mut nonce_bytes).unwrap();
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .expect("encryption under a fresh nonce cannot fail");
+
+    stream
+        .write_all(&(ciphertext.len() as u32).to_le_bytes())
+        .await?;
+    stream.write_all(&nonce_bytes).await?;
+    stream.write_all(&ciphertext).await
+}
+
+/// Reads and decrypts a payload written by `write_encrypted`.
+pub async fn read_encrypted<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    session_key: &[u8; 32],
+) -> std::io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 4];
+    stream.read_exact(&mut len_bytes).await?;
+    let len = u32::from_le_bytes(len_bytes) as usize;
+
+    let mut nonce_bytes = [0u8; 12];
+    stream.read_exact(&mut nonce_bytes).await?;
+
+    let mut ciphertext = vec![0u8; len];
+    stream.read_exact(&mut ciphertext).await?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(session_key));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce_bytes), ciphertext.as_slice())
+        .map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "handshake decryption failed")
+        })
+}
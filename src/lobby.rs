@@ -0,0 +1,91 @@
+/// Coarse phase of a match, queryable before committing to a full netcode
+/// handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    Waiting,
+    Running,
+    Finished,
+}
+
+impl GameState {
+    fn to_byte(self) -> u8 {
+        match self {
+            GameState::Waiting => 0,
+            GameState::Running => 1,
+            GameState::Finished => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(GameState::Waiting),
+            1 => Some(GameState::Running),
+            2 => Some(GameState::Finished),
+            _ => None,
+        }
+    }
+}
+
+/// What a `QueryInfo` request gets back: enough for a launcher to show a
+/// populated server list (seed, fill level, phase) before requesting a full
+/// `ConnectToken`.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerQueryInfo {
+    pub seed: u32,
+    pub max_game_seconds: u32,
+    pub max_players: u8,
+    pub current_players: u8,
+    pub state: GameState,
+}
+
+pub const SERVER_QUERY_INFO_LEN: usize = 4 + 4 + 1 + 1 + 1;
+
+impl ServerQueryInfo {
+    pub fn to_bytes(self) -> [u8; SERVER_QUERY_INFO_LEN] {
+        let mut buf = [0u8; SERVER_QUERY_INFO_LEN];
+        buf[0..4].copy_from_slice(&self.seed.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.max_game_seconds.to_le_bytes());
+        buf[8] = self.max_players;
+        buf[9] = self.current_players;
+        buf[10] = self.state.to_byte();
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; SERVER_QUERY_INFO_LEN]) -> Option<Self> {
+        Some(Self {
+            seed: u32::from_le_bytes(buf[0..4].try_into().ok()?),
+            max_game_seconds: u32::from_le_bytes(buf[4..8].try_into().ok()?),
+            max_players: buf[8],
+            current_players: buf[9],
+            state: GameState::from_byte(buf[10])?,
+        })
+    }
+}
+
+/// The first byte a client sends on the auth TCP connection, ahead of the
+/// existing `ConnectToken` framing: `Token` gets the normal handshake,
+/// `QueryInfo` gets a fixed-size (`SERVER_QUERY_INFO_LEN`-byte)
+/// `ServerQueryInfo` back instead, with no length prefix, so a
+/// launcher can enumerate servers without committing to a full connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthRequest {
+    Token,
+    QueryInfo,
+}
+
+impl AuthRequest {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            AuthRequest::Token => 0,
+            AuthRequest::QueryInfo => 1,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(AuthRequest::Token),
+            1 => Some(AuthRequest::QueryInfo),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use bevy::render::{
+    mesh::{Indices, Mesh, VertexAttributeValues},
+    render_resource::PrimitiveTopology,
+};
+
+/// Writes `mesh` as a binary STL file: an 80-byte zero header, a `u32` triangle
+/// count, then per triangle a recomputed face normal followed by its three
+/// vertex positions (all little-endian `f32`) and a trailing `u16` attribute
+/// byte count of 0.
+pub fn write_stl(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let (positions, _uvs, _normals, indices) = triangle_list(mesh);
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+
+    file.write_all(&[0u8; 80])?;
+    file.write_all(&((indices.len() / 3) as u32).to_le_bytes())?;
+
+    for triangle in indices.chunks_exact(3) {
+        let p0 = positions[triangle[0] as usize];
+        let p1 = positions[triangle[1] as usize];
+        let p2 = positions[triangle[2] as usize];
+        let normal = face_normal(p0, p1, p2);
+
+        for v in [normal, p0, p1, p2] {
+            for c in v {
+                file.write_all(&c.to_le_bytes())?;
+            }
+        }
+        file.write_all(&0u16.to_le_bytes())?;
+    }
+
+    file.flush()
+}
+
+/// Writes `mesh` as a Wavefront OBJ: `v`/`vn`/`vt` lines followed by
+/// triangulated `f a/t/n` faces reusing the mesh's own index buffer.
+pub fn write_obj(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let (positions, uvs, normals, indices) = triangle_list(mesh);
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+
+    for p in &positions {
+        writeln!(file, "v {} {} {}", p[0], p[1], p[2])?;
+    }
+    for uv in &uvs {
+        writeln!(file, "vt {} {}", uv[0], uv[1])?;
+    }
+    for n in &normals {
+        writeln!(file, "vn {} {} {}", n[0], n[1], n[2])?;
+    }
+    for triangle in indices.chunks_exact(3) {
+        // OBJ indices are 1-based.
+        let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+        writeln!(file, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+
+    file.flush()
+}
+
+fn face_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
+    let e1 = sub(p1, p0);
+    let e2 = sub(p2, p0);
+    let n = cross(e1, e2);
+    normalize(n)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len < 1e-8 {
+        return [0.0, 0.0, 0.0];
+    }
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+type TriangleList = (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<[f32; 3]>, Vec<u32>);
+
+/// Pulls positions/uvs/normals/indices out of `mesh`, using a sequential
+/// `0..n` index buffer if the mesh has none. Panics if `mesh` isn't a `TriangleList`, since that's
+/// all this crate's track meshes ever produce.
+fn triangle_list(mesh: &Mesh) -> TriangleList {
+    assert_eq!(
+        mesh.primitive_topology(),
+        PrimitiveTopology::TriangleList,
+        "mesh export only supports triangle lists"
+    );
+
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(p)) => p.clone(),
+        _ => Vec::new(),
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uv)) => uv.clone(),
+        _ => vec![[0.0, 0.0]; positions.len()],
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(n)) => n.clone(),
+        _ => vec![[0.0, 1.0, 0.0]; positions.len()],
+    };
+    let indices = match mesh.indices() {
+        Some(Indices::U32(i)) => i.clone(),
+        Some(Indices::U16(i)) => i.iter().map(|&i| i as u32).collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    (positions, uvs, normals, indices)
+}
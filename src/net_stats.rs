@@ -0,0 +1,129 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiPlugin, egui};
+use lightyear::prelude::{ClientId::Netcode, server::ConnectionManager};
+
+use crate::ClientIds;
+
+/// Number of samples kept per metric per client, so the sparklines cover a
+/// few seconds of history without growing unbounded over a long match.
+const HISTORY_LEN: usize = 150;
+
+/// Live per-client connection health, rendered as an egui overlay on the
+/// server window when `--net-stats` is passed. Off by default: sampling and
+/// drawing a panel every frame isn't free, and most runs don't need it.
+pub struct NetworkStatsPlugin;
+
+impl Plugin for NetworkStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(EguiPlugin {
+            enable_multipass_for_primary_context: false,
+        });
+        app.init_resource::<ClientNetStats>();
+        app.add_systems(Update, (sample_network_stats, render_network_panel).chain());
+    }
+}
+
+#[derive(Default)]
+struct ClientSampleHistory {
+    rtt_ms: VecDeque<f32>,
+    jitter_ms: VecDeque<f32>,
+    bytes_in_per_sec: VecDeque<f32>,
+    bytes_out_per_sec: VecDeque<f32>,
+    packet_loss_pct: VecDeque<f32>,
+    last_bytes_sent: u64,
+    last_bytes_received: u64,
+}
+
+fn push_capped(buf: &mut VecDeque<f32>, sample: f32) {
+    buf.push_back(sample);
+    if buf.len() > HISTORY_LEN {
+        buf.pop_front();
+    }
+}
+
+#[derive(Resource, Default)]
+struct ClientNetStats(HashMap<u64, ClientSampleHistory>);
+
+fn sample_network_stats(
+    client_ids: Res<ClientIds>,
+    connection_manager: Res<ConnectionManager>,
+    mut stats: ResMut<ClientNetStats>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs().max(f32::EPSILON);
+    let ids: Vec<u64> = client_ids.0.read().unwrap().keys().copied().collect();
+    stats.0.retain(|id, _| ids.contains(id));
+
+    for id in ids {
+        let Ok(connection) = connection_manager.connection(Netcode(id)) else {
+            continue;
+        };
+        let entry = stats.0.entry(id).or_default();
+
+        push_capped(&mut entry.rtt_ms, connection.rtt().as_secs_f32() * 1000.0);
+        push_capped(&mut entry.jitter_ms, connection.jitter().as_secs_f32() * 1000.0);
+
+        let connection_stats = connection.stats();
+        let bytes_in_delta = connection_stats
+            .bytes_received
+            .saturating_sub(entry.last_bytes_received);
+        let bytes_out_delta = connection_stats
+            .bytes_sent
+            .saturating_sub(entry.last_bytes_sent);
+        push_capped(&mut entry.bytes_in_per_sec, bytes_in_delta as f32 / dt);
+        push_capped(&mut entry.bytes_out_per_sec, bytes_out_delta as f32 / dt);
+        push_capped(&mut entry.packet_loss_pct, connection_stats.packet_loss_percent);
+
+        entry.last_bytes_received = connection_stats.bytes_received;
+        entry.last_bytes_sent = connection_stats.bytes_sent;
+    }
+}
+
+fn render_network_panel(mut contexts: EguiContexts, stats: Res<ClientNetStats>) {
+    egui::Window::new("Network Stats").show(contexts.ctx_mut(), |ui| {
+        if stats.0.is_empty() {
+            ui.label("no clients connected");
+        }
+        for (client_id, entry) in stats.0.iter() {
+            ui.collapsing(format!("client {client_id}"), |ui| {
+                ui.label(format!(
+                    "rtt: {:.1} ms   jitter: {:.1} ms   loss: {:.1}%",
+                    entry.rtt_ms.back().copied().unwrap_or_default(),
+                    entry.jitter_ms.back().copied().unwrap_or_default(),
+                    entry.packet_loss_pct.back().copied().unwrap_or_default(),
+                ));
+                sparkline(ui, "rtt (ms)", &entry.rtt_ms);
+                sparkline(ui, "bytes in/s", &entry.bytes_in_per_sec);
+                sparkline(ui, "bytes out/s", &entry.bytes_out_per_sec);
+            });
+        }
+    });
+}
+
+/// Hand-rolled sparkline: normalizes `samples` to the allocated rect's height
+/// and draws them as a polyline, since this tree doesn't otherwise depend on
+/// a plotting crate just for this one panel.
+fn sparkline(ui: &mut egui::Ui, label: &str, samples: &VecDeque<f32>) {
+    ui.label(label);
+    let desired_size = egui::vec2(ui.available_width(), 32.0);
+    let (rect, _response) = ui.allocate_exact_size(desired_size, egui::Sense::hover());
+    if !ui.is_rect_visible(rect) || samples.len() < 2 {
+        return;
+    }
+
+    let max = samples.iter().copied().fold(f32::MIN_POSITIVE, f32::max);
+    let points: Vec<egui::Pos2> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let x = rect.left() + (i as f32 / (HISTORY_LEN - 1) as f32) * rect.width();
+            let y = rect.bottom() - (v / max) * rect.height();
+            egui::pos2(x, y)
+        })
+        .collect();
+
+    ui.painter()
+        .add(egui::Shape::line(points, egui::Stroke::new(1.5, egui::Color32::LIGHT_GREEN)));
+}
@@ -2,17 +2,26 @@ use std::collections::HashMap;
 
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use lightyear::prelude::{Replicated, client::Predicted, server::ControlledBy, server::Replicate};
+use lightyear::prelude::{
+    Replicated, client::Predicted, server::ConnectionManager, server::ControlledBy,
+    server::Replicate,
+};
 
 use crate::{
-    protocol::{Finish, GameResult, PlayerColor, PlayerName, PlayerPosition, VelocityShare},
-    world::{Finished, GravityModifier, LastTouched},
+    chat::broadcast_system_message,
+    protocol::{Finish, GameResult, PlayerColor, PlayerName, PlayerPosition, Spectator, VelocityShare},
+    scripting::{GameScript, ScriptRequestedEnd},
+    world::{Finished, GravityModifier, LastTouched, RespawnPenalty},
 };
 
 pub struct PlayerPlugin {
     pub physics: bool,
     pub player_count: u8,
     pub max_game_seconds: u32,
+    /// Enables `sync_test::sync_test_system`'s per-frame determinism check.
+    /// Only meaningful alongside `physics: true`, since only the
+    /// physics-authoritative side has real Rapier state to validate.
+    pub sync_test: bool,
 }
 
 #[derive(Resource, Debug)]
@@ -36,6 +45,12 @@ impl Plugin for PlayerPlugin {
             app.add_systems(Update, attach_player_model_server);
             app.add_systems(Update, game_end_system);
             app.add_systems(Update, sync_velocity_physics);
+            app.insert_resource(crate::sync_test::SyncTestConfig {
+                enabled: self.sync_test,
+                tolerance: 0.05,
+            });
+            app.insert_resource(crate::sync_test::DesyncCount::default());
+            app.add_systems(FixedUpdate, crate::sync_test::sync_test_system);
         } else {
             app.add_systems(Update, attach_player_model_client);
         }
@@ -108,7 +123,8 @@ fn attach_player_model_server(
                     base_gravity: 1.0,
                     remaining: Timer::from_seconds(0.0, TimerMode::Once),
                     current: 1.0,
-                });
+                })
+                .insert(RespawnPenalty::default());
         }
         if c != 0 {
             game_end_condition.physics_start_time = time.elapsed_secs() as u32;
@@ -120,7 +136,7 @@ fn attach_player_model_server(
 fn attach_player_model_client(
     player_query: Query<
         (&PlayerPosition, &PlayerColor, Entity),
-        (Without<Transform>, Without<Predicted>),
+        (Without<Transform>, Without<Predicted>, Without<Spectator>),
     >,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -136,32 +152,53 @@ fn attach_player_model_client(
     }
 }
 
+/// Renders a `Finish` as the `description` string handed to the rules
+/// script, so a `compute_rankings`/`on_player_finish` hook can parse out
+/// the finish kind without depending on our internal enum layout.
+fn describe_finish(finish: Finish) -> String {
+    match finish {
+        Finish::Time(at) => format!("time:{at:.3}"),
+        Finish::TrackProgress(road_id, at) => format!("track:{road_id}:{at:.3}"),
+    }
+}
+
 fn game_end_system(
     mut game_end_condition: ResMut<GameEndCondition>,
-    players: Query<(&LastTouched, Option<&Finished>, &ControlledBy)>,
+    players: Query<
+        (&LastTouched, Option<&Finished>, Option<&RespawnPenalty>, &ControlledBy),
+        Without<Spectator>,
+    >,
     name_q: Query<(&PlayerName, &Replicated)>,
     time: Res<Time>,
     player_count: Res<SpawnedPlayersCount>,
     mut commands: Commands,
+    mut connection_manager: ResMut<ConnectionManager>,
+    script: Option<Res<GameScript>>,
+    script_requested_end: Option<Res<ScriptRequestedEnd>>,
 ) {
+    let script_requested_end = script_requested_end.is_some_and(|r| r.0);
     let condition = game_end_condition.has_started
         && !game_end_condition.evaluated
         && ((game_end_condition.max_game_seconds + game_end_condition.physics_start_time) as f32
             <= time.elapsed_secs()
-            || game_end_condition.players_finished == player_count.max);
+            || game_end_condition.players_finished == player_count.max
+            || script_requested_end);
     if condition {
         let id_2_name: HashMap<_, _> = name_q.iter().map(|(n, c)| (c.from.unwrap(), n)).collect();
         dbg!(&id_2_name);
         let mut all_players: Vec<_> = players
             .iter()
-            .map(|(l, f, c)| match c.target {
+            .map(|(l, f, penalty, c)| match c.target {
                 lightyear::prelude::NetworkTarget::Single(client_id) => {
                     let name = id_2_name.get(&client_id).unwrap();
                     let f = if let Some(t) = f {
-                        Finish::Time(t.at)
+                        Finish::Time(t.at + penalty.map_or(0.0, |p| p.0))
                     } else {
                         Finish::TrackProgress(l.road_id, l.at)
                     };
+                    if let Some(script) = &script {
+                        script.on_player_finish(client_id.to_bits(), &name.0, &describe_finish(f));
+                    }
                     (name.0.to_string(), f)
                 }
                 _ => panic!(),
@@ -180,7 +217,30 @@ fn game_end_system(
             }
         });
 
+        if let Some(script) = &script {
+            let descriptions: Vec<_> = all_players
+                .iter()
+                .map(|(name, f)| (name.clone(), describe_finish(*f)))
+                .collect();
+            if let Some(order) = script.compute_rankings(&descriptions) {
+                all_players.sort_by_key(|(name, _)| {
+                    order.iter().position(|n| n == name).unwrap_or(usize::MAX)
+                });
+            }
+        }
+
         info!(rankings = ?all_players, "game end ------------------" );
+        let rankings = all_players
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| format!("{}. {name}", i + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        broadcast_system_message(
+            &mut connection_manager,
+            format!("Race finished! Rankings: {rankings}"),
+            true,
+        );
         commands.spawn((
             GameResult {
                 players: all_players,
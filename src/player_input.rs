@@ -1,13 +1,47 @@
-use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, tasks::futures_lite};
 use client::InputManager;
 use lightyear::prelude::*;
 
-use crate::protocol::{Direction, Inputs, PlayerPosition};
+use crate::{
+    ControlViaGrpc,
+    protocol::{Direction, Inputs, PlayerPosition},
+    rollback::RollbackBall,
+};
+
+pub struct PlayerInputPlugin {
+    /// Number of ticks to hold an input in `PendingInputs` before it is applied,
+    /// giving remote agents a fixed, known delay instead of whatever the
+    /// network/render timing happens to produce.
+    pub input_delay: u16,
+}
+
+#[derive(Resource)]
+struct InputDelay(u16);
 
-pub struct PlayerInputPlugin;
+/// Ring buffer of `(tick read, input)` pairs waiting out `InputDelay` before
+/// being submitted to the `InputManager`.
+#[derive(Resource, Default)]
+struct PendingInputs(VecDeque<(u32, Inputs)>);
+
+/// The input read from the keyboard (or the gRPC agent) this tick, before it enters the delay
+/// queue. `rollback::apply_predicted_input` uses this to drive the local
+/// ball's prediction on ticks it isn't replaying a reconciliation.
+#[derive(Resource, Clone)]
+pub struct LastLocalInput(pub Inputs);
+
+impl Default for LastLocalInput {
+    fn default() -> Self {
+        Self(Inputs::None)
+    }
+}
 
 impl Plugin for PlayerInputPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(InputDelay(self.input_delay));
+        app.init_resource::<PendingInputs>();
+        app.init_resource::<LastLocalInput>();
         app.add_systems(Update, handle_input);
         app.add_systems(Update, sync_positions);
     }
@@ -17,34 +51,66 @@ fn handle_input(
     tick_manager: Res<TickManager>,
     mut input_manager: ResMut<InputManager<Inputs>>,
     keypress: Res<ButtonInput<KeyCode>>,
+    delay: Res<InputDelay>,
+    mut pending: ResMut<PendingInputs>,
+    mut last_local_input: ResMut<LastLocalInput>,
+    grpc: Option<Res<ControlViaGrpc>>,
 ) {
     let tick = tick_manager.tick();
-    let mut input = Inputs::None;
-    let mut direction = Direction {
-        forward: false,
-        back: false,
-        left: false,
-        right: false,
+
+    // A gRPC-controlled agent has no keyboard to read: drive it from whatever
+    // `Input` RPC last wrote into its `current_input` mutex instead.
+    let input = if let Some(grpc) = grpc.filter(|g| g.enabled) {
+        futures_lite::future::block_on(async { grpc.agent.current_input.lock().await.clone() })
+    } else {
+        let mut input = Inputs::None;
+        let mut direction = Direction {
+            forward: false,
+            back: false,
+            left: false,
+            right: false,
+            reset: false,
+        };
+        if keypress.pressed(KeyCode::KeyW) || keypress.pressed(KeyCode::ArrowUp) {
+            direction.forward = true;
+        }
+        if keypress.pressed(KeyCode::KeyS) || keypress.pressed(KeyCode::ArrowDown) {
+            direction.back = true;
+        }
+        if keypress.pressed(KeyCode::KeyA) || keypress.pressed(KeyCode::ArrowLeft) {
+            direction.left = true;
+        }
+        if keypress.pressed(KeyCode::KeyD) || keypress.pressed(KeyCode::ArrowRight) {
+            direction.right = true;
+        }
+        if keypress.just_pressed(KeyCode::KeyR) {
+            direction.reset = true;
+        }
+        if direction.is_some() {
+            input = Inputs::Direction(direction);
+        }
+        input
     };
-    if keypress.pressed(KeyCode::KeyW) || keypress.pressed(KeyCode::ArrowUp) {
-        direction.forward = true;
-    }
-    if keypress.pressed(KeyCode::KeyS) || keypress.pressed(KeyCode::ArrowDown) {
-        direction.back = true;
-    }
-    if keypress.pressed(KeyCode::KeyA) || keypress.pressed(KeyCode::ArrowLeft) {
-        direction.left = true;
-    }
-    if keypress.pressed(KeyCode::KeyD) || keypress.pressed(KeyCode::ArrowRight) {
-        direction.right = true;
-    }
-    if direction.is_some() {
-        input = Inputs::Direction(direction);
+
+    last_local_input.0 = input.clone();
+    let now = tick.0 as u32;
+    pending.0.push_back((now, input));
+    while let Some(&(read_at, _)) = pending.0.front() {
+        if (now.wrapping_sub(read_at) as u16) < delay.0 {
+            break;
+        }
+        let (_, queued) = pending.0.pop_front().unwrap();
+        input_manager.add_input(queued, tick);
     }
-    input_manager.add_input(input, tick)
 }
 
-fn sync_positions(mut players: Query<(&mut Transform, &PlayerPosition)>) {
+/// Places everyone else at their replicated position directly. The local
+/// predicted ball is excluded: its `Transform` is driven by its own Rapier
+/// simulation in `rollback`, and stomping it here every frame would undo the
+/// entire point of predicting locally instead of rendering on arrival.
+fn sync_positions(
+    mut players: Query<(&mut Transform, &PlayerPosition), Without<RollbackBall>>,
+) {
     for (mut transform, position) in players.iter_mut() {
         *transform = transform
             .with_translation(position.0)
@@ -36,6 +36,14 @@ pub struct PlayerColor(pub Color);
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PlayerName(pub String);
 
+/// Marks a replicated player entity as a spectator: a client that connected
+/// after the match already started. Spectators get a free-fly camera instead
+/// of a ball in `attach_player_model_client` and are left out of
+/// `SpawnedPlayersCount`/`GameEndCondition` so they can't corrupt the
+/// start/end conditions of the match they're watching.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Spectator;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Direction {
     pub forward: bool,
@@ -51,18 +59,41 @@ impl Direction {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Inputs {
     Direction(Direction),
+    /// Continuous control for remote agents: `steer` and `throttle` are both
+    /// in `-1.0..=1.0`, applied as proportional torque instead of the fixed
+    /// magnitude used by `Direction`. Keyboard play always sends `Direction`.
+    Analog { steer: f32, throttle: f32 },
     Spawn,
     None,
 }
 
+/// Client-to-server player chat and server-to-client announcements
+/// ("2 players joined, waiting for 4", "Player X crossed the finish line").
+/// There's no persistent chat history component to replicate here; this is a
+/// fire-and-forget message rather than state, so it rides its own channel
+/// instead of going through `register_component`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    /// `None` for a server-authored system announcement; `Some(name)` once
+    /// the server has stamped a relayed player chat line with its sender.
+    pub sender: Option<String>,
+    pub body: String,
+    /// Picks the transient on-screen banner over the scrolling chat log,
+    /// e.g. for a finish-line announcement vs. ordinary player chat.
+    pub overlay: bool,
+}
+
 pub struct ProtocolPlugin;
 
 #[derive(Channel)]
 pub struct Channel1;
 
+#[derive(Channel)]
+pub struct ChatChannel;
+
 impl Plugin for ProtocolPlugin {
     fn build(&self, app: &mut App) {
         app.register_component::<PlayerId>(ChannelDirection::ServerToClient)
@@ -82,6 +113,10 @@ impl Plugin for ProtocolPlugin {
             .add_prediction(client::ComponentSyncMode::Full)
             .add_interpolation(client::ComponentSyncMode::Once);
 
+        app.register_component::<Spectator>(ChannelDirection::ServerToClient)
+            .add_prediction(client::ComponentSyncMode::Once)
+            .add_interpolation(client::ComponentSyncMode::Once);
+
         app.register_component::<GameResult>(ChannelDirection::ServerToClient)
             .add_prediction(client::ComponentSyncMode::Full)
             .add_interpolation(client::ComponentSyncMode::Once);
@@ -92,9 +127,16 @@ impl Plugin for ProtocolPlugin {
 
         app.add_plugins(InputPlugin::<Inputs>::default());
 
+        app.register_message::<ChatMessage>(ChannelDirection::Bidirectional);
+
         app.add_channel::<Channel1>(ChannelSettings {
             mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
             ..Default::default()
         });
+
+        app.add_channel::<ChatChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            ..Default::default()
+        });
     }
 }
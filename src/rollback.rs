@@ -0,0 +1,252 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use lightyear::prelude::TickManager;
+use lightyear::prelude::client::Predicted;
+use lightyear::shared::replication::components::Controlled;
+
+use crate::{
+    player_input::LastLocalInput,
+    protocol::{Inputs, PlayerColor, PlayerPosition},
+    world::{GravityModifier, LastTouched, RespawnPenalty},
+};
+
+/// Positional drift beyond this (in world units), once a stored prediction is
+/// compared against the matching authoritative snapshot, is treated as a real
+/// misprediction rather than harmless float noise.
+const RECONCILE_EPSILON: f32 = 0.05;
+
+/// How many fixed ticks of predicted state a ball keeps around, read from
+/// `RollbackPlugin::prediction_window`. Past this window we assume the
+/// server has long since moved on, so an incoming snapshot older than this is
+/// stale rather than something worth replaying against.
+#[derive(Resource)]
+struct PredictionWindow(usize);
+
+/// Gives each client its own locally-simulated ball instead of just rendering
+/// it wherever the last replicated `PlayerPosition` landed. The predicted
+/// ball keeps a short history of its own motion so a late authoritative
+/// snapshot can be reconciled by correcting that history forward, rather
+/// than snapping and losing the input the player already made.
+pub struct RollbackPlugin {
+    /// How many fixed ticks of predicted state to keep, i.e. how far back a
+    /// late authoritative snapshot can still be reconciled against instead
+    /// of being treated as too stale to bother with.
+    pub prediction_window: usize,
+}
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PredictionWindow(self.prediction_window));
+        app.add_systems(Update, attach_rollback_ball);
+        // These three have to run at the fixed-tick rate, not once per render
+        // frame: `record_predicted_frame` stamps each entry with the tick it
+        // was recorded on, and that stamp is only meaningful -- one entry per
+        // tick, none skipped or doubled -- if it's taken from `FixedUpdate`
+        // rather than `PostUpdate`, which can run any number of times per tick
+        // depending on render framerate.
+        app.add_systems(
+            FixedUpdate,
+            (apply_predicted_input, record_predicted_frame, reconcile_with_server).chain(),
+        );
+    }
+}
+
+/// Marks this client's own `Predicted` ball: the one entity we give a Rapier
+/// body and drive with local prediction, instead of leaving it to
+/// `attach_player_model_client`/`attach_player_model_server`, which both
+/// filter `Without<Predicted>` and so never touch it.
+#[derive(Component)]
+pub struct RollbackBall;
+
+/// One fixed tick of predicted state, recorded in `FixedUpdate` right after
+/// the previous Rapier step: the tick it was recorded on and the transform/velocities, so a
+/// later correction can tell exactly which buffered entry a given snapshot
+/// confirms or refutes and, if it doesn't, carry the correction forward
+/// across the rest of the buffer.
+#[derive(Clone)]
+struct FrameState {
+    frame: u32,
+    pos: Vec3,
+    rot: Quat,
+    linvel: Vec3,
+    angvel: Vec3,
+}
+
+/// Ring buffer of this ball's own `FrameState` history, bounded to
+/// `PredictionWindow`.
+#[derive(Component, Default)]
+struct PredictionHistory(VecDeque<FrameState>);
+
+/// Gives this client's own predicted ball a physics body and a mesh the
+/// first time it shows up. Neither of the existing `attach_player_model_*`
+/// systems will do this for it, since both deliberately skip `Predicted`
+/// entities to leave room for exactly this path.
+fn attach_rollback_ball(
+    player_query: Query<
+        (Entity, &PlayerPosition, &PlayerColor),
+        (With<Predicted>, With<Controlled>, Without<RollbackBall>),
+    >,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, position, color) in &player_query {
+        commands.entity(entity).insert((
+            RollbackBall,
+            PredictionHistory::default(),
+            Mesh3d(meshes.add(Sphere::new(0.5))),
+            MeshMaterial3d(materials.add(color.0)),
+            Transform::from_translation(position.0).with_rotation(position.1),
+            Collider::ball(0.5),
+            RigidBody::Dynamic,
+            Velocity::default(),
+            ExternalForce::default(),
+            GravityScale(1.0),
+            Ccd::enabled(),
+            LastTouched {
+                road_id: 0,
+                at: 0.0,
+                touching: false,
+            },
+            GravityModifier {
+                base_gravity: 1.0,
+                remaining: Timer::from_seconds(0.0, TimerMode::Once),
+                current: 1.0,
+            },
+            RespawnPenalty::default(),
+        ));
+    }
+}
+
+/// Feeds this tick's local input into the predicted ball's `ExternalForce`.
+/// This is a simplified stand-in for the server's `torque_function` (no
+/// last-velocity smoothing) since any drift it introduces is exactly what
+/// `reconcile_with_server` exists to correct.
+fn apply_predicted_input(
+    local_input: Res<LastLocalInput>,
+    mut balls: Query<(&Velocity, &mut ExternalForce), With<RollbackBall>>,
+) {
+    for (velocity, mut force) in &mut balls {
+        apply_steering_torque(velocity, &mut force, &local_input.0);
+    }
+}
+
+fn apply_steering_torque(velocity: &Velocity, force: &mut ExternalForce, input: &Inputs) {
+    let up = Vec3::Y;
+    let forward = velocity.linvel.try_normalize().unwrap_or(Vec3::Z);
+    let forward_torque = up.cross(forward).normalize_or_zero();
+    let multiplier = 2.0f32;
+
+    force.torque = match input {
+        Inputs::Direction(direction) => {
+            let mut torque = Vec3::ZERO;
+            if direction.forward {
+                torque += forward_torque;
+            }
+            if direction.back {
+                torque -= forward_torque;
+            }
+            if direction.left {
+                torque += Quat::from_rotation_y(PI * 0.5) * forward_torque;
+            }
+            if direction.right {
+                torque += Quat::from_rotation_y(-PI * 0.5) * forward_torque;
+            }
+            torque * multiplier
+        }
+        Inputs::Analog { steer, throttle } => {
+            let turn_angle = steer.clamp(-1.0, 1.0) * PI * 0.75;
+            Quat::from_rotation_y(-turn_angle)
+                * forward_torque
+                * multiplier
+                * throttle.clamp(-1.0, 1.0)
+        }
+        Inputs::None | Inputs::Spawn => Vec3::ZERO,
+    };
+}
+
+/// Snapshots the ball's state as the previous tick's Rapier step left it
+/// (Rapier runs in `FixedPostUpdate`, after this `FixedUpdate` system),
+/// stamped with the current tick, discarding anything past `PredictionWindow`.
+fn record_predicted_frame(
+    tick_manager: Res<TickManager>,
+    window: Res<PredictionWindow>,
+    mut balls: Query<(&Transform, &Velocity, &mut PredictionHistory), With<RollbackBall>>,
+) {
+    let frame = tick_manager.tick().0 as u32;
+    for (transform, velocity, mut history) in &mut balls {
+        history.0.push_back(FrameState {
+            frame,
+            pos: transform.translation,
+            rot: transform.rotation,
+            linvel: velocity.linvel,
+            angvel: velocity.angvel,
+        });
+        while history.0.len() > window.0 {
+            history.0.pop_front();
+        }
+    }
+}
+
+/// Corrects a misprediction: when a fresh authoritative snapshot disagrees
+/// with the buffered prediction it confirms by more than `RECONCILE_EPSILON`,
+/// the correction is applied immediately rather than smeared across the next
+/// `history.len()` real ticks.
+///
+/// A literal re-simulation -- reapplying each buffered input through the
+/// real movement system and stepping Rapier again for every tick since the
+/// confirmed frame -- isn't something we can scope to just this one ball:
+/// Rapier's step advances every dynamic body in the scene at once (every
+/// other predicted ball, every gravity booster), so manually stepping it
+/// again here would double-simulate all of them too. What we *do* have is
+/// the history this ball's own, real Rapier step already produced for every
+/// tick since the confirmed frame -- so instead of replaying inputs, correct
+/// it directly: take the position/rotation error the server just revealed at
+/// the oldest (confirmed) frame, and carry that same error forward onto
+/// every later buffered frame, then adopt the corrected latest frame as the
+/// ball's state right now. `FrameState` is stamped with the tick it was
+/// recorded on (see `record_predicted_frame`), which is now guaranteed to
+/// advance exactly one tick at a time since this whole chain runs in
+/// `FixedUpdate`, so we can also drop anything that's already aged out of
+/// `PredictionWindow` before trusting the front of the queue as "confirmed".
+fn reconcile_with_server(
+    tick_manager: Res<TickManager>,
+    window: Res<PredictionWindow>,
+    mut balls: Query<
+        (&PlayerPosition, &mut Transform, &mut Velocity, &mut PredictionHistory),
+        (With<RollbackBall>, Changed<PlayerPosition>),
+    >,
+) {
+    let current_frame = tick_manager.tick().0 as u32;
+    for (server_pos, mut transform, mut velocity, mut history) in &mut balls {
+        while history
+            .0
+            .front()
+            .is_some_and(|f| current_frame.saturating_sub(f.frame) as usize > window.0)
+        {
+            history.0.pop_front();
+        }
+
+        let Some(confirmed) = history.0.front().cloned() else {
+            continue;
+        };
+        let position_error = server_pos.0 - confirmed.pos;
+        if position_error.length() <= RECONCILE_EPSILON {
+            continue;
+        }
+        let rotation_error = server_pos.1 * confirmed.rot.inverse();
+
+        for frame in history.0.iter_mut() {
+            frame.pos += position_error;
+            frame.rot = rotation_error * frame.rot;
+        }
+
+        let corrected = history.0.back().cloned().unwrap_or(confirmed);
+        transform.translation = corrected.pos;
+        transform.rotation = corrected.rot;
+        velocity.linvel = corrected.linvel;
+        velocity.angvel = corrected.angvel;
+    }
+}
@@ -0,0 +1,133 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use bevy::prelude::*;
+use mlua::{Function, Lua, Table};
+
+use crate::protocol::{PlayerName, PlayerPosition};
+
+/// Loads a Lua rules script (`--rules-script <path>`) and exposes it to the
+/// rest of the server as the `GameScript` resource, turning the fixed-timer
+/// race mode into a host for custom game modes without recompiling.
+///
+/// Hooks the script may define, all optional:
+/// - `on_player_join(client_id)`
+/// - `on_player_finish(client_id, name, description)`
+/// - `on_tick(elapsed, positions) -> bool` -- return `true` to end the match
+///   right now instead of waiting for the timer/all-finished condition.
+/// - `compute_rankings(players) -> ordered players` -- `players` and the
+///   return value are both arrays of `{name, description}` tables; omit this
+///   hook to keep the built-in time/track-progress sort.
+pub struct ScriptPlugin {
+    pub script_path: Option<PathBuf>,
+}
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(path) = self.script_path.clone() else {
+            return;
+        };
+        let source = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read rules script {path:?}: {e}"));
+        let lua = Lua::new();
+        lua.load(&source)
+            .exec()
+            .unwrap_or_else(|e| panic!("failed to run rules script {path:?}: {e}"));
+
+        app.insert_resource(GameScript(Mutex::new(lua)));
+        app.init_resource::<ScriptRequestedEnd>();
+        app.add_systems(Update, script_on_tick);
+    }
+}
+
+/// Set by `on_tick` when the script's own win condition fires. Checked by
+/// `player::game_end_system` alongside the built-in timer/all-finished check.
+#[derive(Resource, Default)]
+pub struct ScriptRequestedEnd(pub bool);
+
+#[derive(Resource)]
+pub struct GameScript(Mutex<Lua>);
+
+impl GameScript {
+    pub fn on_player_join(&self, client_id: u64) {
+        self.call_hook("on_player_join", client_id);
+    }
+
+    pub fn on_player_finish(&self, client_id: u64, name: &str, description: &str) {
+        self.call_hook(
+            "on_player_finish",
+            (client_id, name.to_string(), description.to_string()),
+        );
+    }
+
+    fn call_hook<A: mlua::IntoLuaMulti>(&self, name: &str, args: A) {
+        let lua = self.0.lock().unwrap();
+        let Ok(Some(function)) = lua.globals().get::<Option<Function>>(name) else {
+            return;
+        };
+        if let Err(e) = function.call::<()>(args) {
+            warn!("rules script hook `{name}` errored: {e}");
+        }
+    }
+
+    /// Calls `compute_rankings(players)` with each finisher's `(name,
+    /// description)` and returns the script's name ordering, or `None` if
+    /// the script doesn't define the hook -- the caller should fall back to
+    /// its own sort in that case.
+    pub fn compute_rankings(&self, players: &[(String, String)]) -> Option<Vec<String>> {
+        let lua = self.0.lock().unwrap();
+        let function = lua
+            .globals()
+            .get::<Option<Function>>("compute_rankings")
+            .ok()
+            .flatten()?;
+
+        let input = lua.create_table().ok()?;
+        for (i, (name, description)) in players.iter().enumerate() {
+            let entry = lua.create_table().ok()?;
+            entry.set("name", name.as_str()).ok()?;
+            entry.set("description", description.as_str()).ok()?;
+            input.set(i + 1, entry).ok()?;
+        }
+
+        let ranked: Table = function.call(input).ok()?;
+        let mut names = Vec::new();
+        for pair in ranked.sequence_values::<Table>() {
+            let Ok(entry) = pair else { break };
+            if let Ok(name) = entry.get::<String>("name") {
+                names.push(name);
+            }
+        }
+        Some(names)
+    }
+}
+
+fn script_on_tick(
+    script: Res<GameScript>,
+    mut requested_end: ResMut<ScriptRequestedEnd>,
+    players: Query<(&PlayerName, &PlayerPosition)>,
+    time: Res<Time>,
+) {
+    let lua = script.0.lock().unwrap();
+    let Ok(Some(function)) = lua.globals().get::<Option<Function>>("on_tick") else {
+        return;
+    };
+
+    let Ok(positions) = lua.create_table() else {
+        return;
+    };
+    for (i, (name, position)) in players.iter().enumerate() {
+        let Ok(entry) = lua.create_table() else {
+            continue;
+        };
+        let _ = entry.set("name", name.0.as_str());
+        let _ = entry.set("x", position.0.x);
+        let _ = entry.set("y", position.0.y);
+        let _ = entry.set("z", position.0.z);
+        let _ = positions.set(i + 1, entry);
+    }
+
+    match function.call::<bool>((time.elapsed_secs(), positions)) {
+        Ok(should_end) => requested_end.0 = requested_end.0 || should_end,
+        Err(e) => warn!("rules script `on_tick` errored: {e}"),
+    }
+}
@@ -1,16 +1,27 @@
+mod chat;
 mod config;
+mod handshake;
+mod lobby;
+mod mc_tables;
+mod mesh_export;
+mod net_stats;
 mod player;
 mod protocol;
+mod scripting;
 mod server_cam;
 mod server_input;
+mod sync_test;
+mod terrain;
 mod track_gen;
 mod track_mesh;
+mod vfx;
 mod world;
 
 use std::{
     collections::HashMap,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use async_compat::Compat;
@@ -21,20 +32,27 @@ use bevy::{
     window::{CursorGrabMode, WindowResolution},
 };
 use bevy_rapier3d::prelude::*;
+use chat::{ChatPlugin, broadcast_system_message};
 use clap::Parser;
-use config::shared_config;
+use config::{TICK_RATE_HZ, shared_config};
+use handshake::{generate_static_keypair, key_from_hex, key_to_hex, server_handshake, write_encrypted};
 use lightyear::prelude::server::*;
 use lightyear::prelude::*;
 use lightyear::server::events::{ConnectEvent, DisconnectEvent};
 use lightyear::{connection::netcode::PRIVATE_KEY_BYTES, prelude::ClientId::Netcode};
-use player::{PlayerBundle, PlayerPlugin, SpawnedPlayersCount};
-use protocol::{PlayerColor, PlayerPosition, ProtocolPlugin, VelocityShare};
+use lobby::{AuthRequest, GameState, ServerQueryInfo};
+use net_stats::NetworkStatsPlugin;
+use player::{GameEndCondition, PlayerBundle, PlayerPlugin, SpawnedPlayersCount};
+use protocol::{PlayerColor, PlayerPosition, ProtocolPlugin, Spectator, VelocityShare};
 use rand::{TryRngCore, rngs::OsRng};
+use scripting::{GameScript, ScriptPlugin};
 use server::{IoConfig, NetConfig, NetcodeConfig, ServerCommands, ServerConfig, ServerPlugins};
 use server_cam::{CameraController, CameraControllerPlugin};
 use server_input::ServerInputPlugin;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use vfx::VfxPlugin;
 use world::{LowGpu, Seed, WorldPlugin};
+use x25519_dalek::{PublicKey, StaticSecret};
 
 #[derive(Parser)]
 struct ServerArgs {
@@ -62,6 +80,41 @@ struct ServerArgs {
     /// Server ip addes. Only required for allowing remote clients to connect to the server. Should match the ip address of your machine in the local network
     #[clap(long)]
     server_ip: Option<String>,
+    /// If set, writes each generated track block's mesh as `block_<i>.stl`/`.obj`
+    /// into this directory, for 3D printing or importing into tools like Blender.
+    #[clap(long)]
+    export_track_dir: Option<std::path::PathBuf>,
+    /// GGRS-style sync test: every fixed tick, predicts each ball's state from
+    /// the previous tick's saved snapshot and, if that prediction disagrees
+    /// with what Rapier actually produced, logs a warning with the frame
+    /// number and bumps `DesyncCount`; it does not stop the server. Useful
+    /// for catching physics nondeterminism before it causes client/server
+    /// desyncs, but adds overhead, so it's off by default.
+    #[clap(long)]
+    sync_test: bool,
+    /// Shows a live per-client connection health overlay (RTT, jitter,
+    /// bytes in/out per second, packet loss) in an egui panel on the server
+    /// window. Has no effect with `--headless`, since there's no window to
+    /// draw it on.
+    #[clap(long)]
+    net_stats: bool,
+    /// Path to a Lua rules script. When set, the script's `on_tick` hook can
+    /// end the match early and its `compute_rankings` hook replaces the
+    /// time/track-progress sort. The fixed `--max-game-seconds` timer and the
+    /// all-finished check still end the match as well; the script cannot
+    /// extend or disable them.
+    /// See `ScriptPlugin` for the full hook list.
+    #[clap(long)]
+    rules_script: Option<std::path::PathBuf>,
+    /// Hex-encoded 32-byte X25519 static secret key used to authenticate
+    /// this server to clients during the pre-`ConnectToken` handshake (see
+    /// `handshake.rs`). For a real deployment, generate one once out of band
+    /// (e.g. `openssl rand -hex 32`) and keep it stable across restarts so
+    /// clients' pinned `--server-public-key` keeps matching. If unset, a
+    /// fresh keypair is generated at startup and its public half is printed
+    /// so it can be copied to clients for this run.
+    #[clap(long)]
+    static_secret_key: Option<String>,
 }
 
 pub fn main() {
@@ -75,6 +128,21 @@ pub fn main() {
         None => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, game_port)),
     };
 
+    let static_secret = match args.static_secret_key {
+        Some(hex) => StaticSecret::from(
+            key_from_hex(&hex).unwrap_or_else(|e| panic!("invalid --static-secret-key: {e}")),
+        ),
+        None => {
+            let secret = generate_static_keypair();
+            info!(
+                "no --static-secret-key given, generated one for this run; pass \
+                 --server-public-key {} to clients to authenticate this server",
+                key_to_hex(&PublicKey::from(&secret).to_bytes())
+            );
+            secret
+        }
+    };
+
     let server_plugin = ServerPlugin {
         protocol_id: 0,
         private_key: key,
@@ -85,6 +153,11 @@ pub fn main() {
         seed: args.seed,
         low_gpu: args.low_gpu,
         headless: args.headless,
+        export_track_dir: args.export_track_dir,
+        sync_test: args.sync_test,
+        net_stats: args.net_stats,
+        rules_script: args.rules_script,
+        static_secret,
     };
 
     let mut app = App::new();
@@ -109,13 +182,64 @@ struct ServerPlugin {
     seed: u32,
     low_gpu: bool,
     headless: bool,
+    export_track_dir: Option<std::path::PathBuf>,
+    sync_test: bool,
+    net_stats: bool,
+    rules_script: Option<std::path::PathBuf>,
+    /// This server's X25519 identity used to authenticate it to clients
+    /// during the pre-`ConnectToken` handshake. Never logged or sent
+    /// anywhere but through `server_handshake`'s ECDH.
+    static_secret: StaticSecret,
 }
+/// A connected client's replicated entity and, if it's racing rather than
+/// spectating, the `distribute_space` lane it currently occupies. Kept
+/// around so `handle_disconnect_event` can free the lane and renumber
+/// everyone still waiting instead of leaving a gap.
+struct ConnectedClient {
+    entity: Entity,
+    lane: Option<u8>,
+}
+
 #[derive(Resource)]
-struct ClientIds(Arc<RwLock<HashMap<u64, Entity>>>);
+struct ClientIds(Arc<RwLock<HashMap<u64, ConnectedClient>>>);
+
+/// Current player count and coarse game phase, kept in sync with the ECS
+/// world each frame so the auth listener's async `QueryInfo` handler (which
+/// runs outside Bevy's scheduler, on `IoTaskPool`) has something to read.
+#[derive(Clone, Copy)]
+struct LobbySnapshot {
+    current_players: u8,
+    state: GameState,
+}
+
+#[derive(Resource)]
+struct LobbyState(Arc<RwLock<LobbySnapshot>>);
+
+fn sync_lobby_state(
+    lobby_state: Res<LobbyState>,
+    player_count: Res<SpawnedPlayersCount>,
+    game_end_condition: Res<GameEndCondition>,
+) {
+    let state = if game_end_condition.evaluated {
+        GameState::Finished
+    } else if game_end_condition.has_started {
+        GameState::Running
+    } else {
+        GameState::Waiting
+    };
+    *lobby_state.0.write().unwrap() = LobbySnapshot {
+        current_players: player_count.current,
+        state,
+    };
+}
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
-        let client_ids = Arc::new(RwLock::new(HashMap::<u64, Entity>::new()));
+        let client_ids = Arc::new(RwLock::new(HashMap::<u64, ConnectedClient>::new()));
+        let lobby_state = Arc::new(RwLock::new(LobbySnapshot {
+            current_players: 0,
+            state: GameState::Waiting,
+        }));
         if self.headless {
             app.add_plugins(DefaultPlugins.set(WindowPlugin {
                 primary_window: None,
@@ -141,16 +265,32 @@ impl Plugin for ServerPlugin {
         app.insert_resource(Seed(self.seed));
         app.insert_resource(LowGpu(self.low_gpu));
         app.add_plugins(ProtocolPlugin);
+        app.add_plugins(ChatPlugin { is_server: true });
+        app.add_plugins(ScriptPlugin {
+            script_path: self.rules_script.clone(),
+        });
         app.add_plugins(PlayerPlugin {
             physics: true,
             player_count: self.player_count,
             max_game_seconds: self.max_game_seconds,
+            sync_test: self.sync_test,
         });
         app.add_plugins(ServerInputPlugin);
-        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
-        app.add_plugins(WorldPlugin { physics: true });
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule());
+        app.add_plugins(WorldPlugin {
+            physics: true,
+            export_track_dir: self.export_track_dir.clone(),
+            respawn_fall_y: -25.0,
+            respawn_stuck_secs: 5.0,
+            respawn_penalty_secs: 3.0,
+            fixed_hz: TICK_RATE_HZ,
+        });
+        app.add_plugins(VfxPlugin);
         if !self.headless {
             app.add_plugins(CameraControllerPlugin);
+            if self.net_stats {
+                app.add_plugins(NetworkStatsPlugin);
+            }
         }
 
         if self.headless {
@@ -159,6 +299,8 @@ impl Plugin for ServerPlugin {
             app.add_systems(Startup, start_server);
         }
         app.insert_resource(ClientIds(client_ids.clone()));
+        app.insert_resource(LobbyState(lobby_state.clone()));
+        app.add_systems(Update, sync_lobby_state);
 
         app.add_observer(handle_disconnect_event);
         app.add_observer(handle_connect_event);
@@ -168,6 +310,11 @@ impl Plugin for ServerPlugin {
             self.protocol_id,
             self.private_key,
             client_ids.clone(),
+            lobby_state.clone(),
+            self.seed,
+            self.max_game_seconds,
+            self.player_count,
+            self.static_secret.clone(),
         );
     }
 }
@@ -221,30 +368,91 @@ fn start_server(mut commands: Commands, mut windows: Query<&mut Window>) {
 fn start_server_headless(mut commands: Commands, mut windows: Query<&mut Window>) {
     commands.start_server();
 }
-fn handle_disconnect_event(trigger: Trigger<DisconnectEvent>, client_ids: Res<ClientIds>) {
+fn handle_disconnect_event(
+    trigger: Trigger<DisconnectEvent>,
+    client_ids: Res<ClientIds>,
+    mut commands: Commands,
+    mut player_count: ResMut<SpawnedPlayersCount>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut position_q: Query<&mut PlayerPosition>,
+    game_end_condition: Res<GameEndCondition>,
+) {
     if let Netcode(client_id) = trigger.event().client_id {
-        client_ids.0.write().unwrap().remove(&client_id);
+        let removed = client_ids.0.write().unwrap().remove(&client_id);
+        let Some(removed) = removed else { return };
+
+        commands.entity(removed.entity).despawn();
+
+        if removed.lane.is_some() {
+            player_count.current -= 1;
+            // Only compact lanes/teleport starting positions while everyone is
+            // still waiting in the lobby. Once the race has started, renumbering
+            // would yank every other racer's `PlayerPosition` to a new starting-line
+            // X sideways mid-physics-step.
+            if !game_end_condition.has_started {
+                renumber_lanes(&client_ids, player_count.current, player_count.max, &mut position_q);
+            }
+        }
+
+        broadcast_system_message(
+            &mut connection_manager,
+            format!("A player disconnected ({:?}).", trigger.event().reason),
+            false,
+        );
     }
 }
 
+/// Compacts the remaining racers' `lane` indices down to `0..current` and
+/// moves their `PlayerPosition` to match, so a disconnect during the waiting
+/// phase doesn't leave a gap in `distribute_space`'s layout for everyone
+/// still there. Lane width stays based on `max`, not on `current`.
+fn renumber_lanes(
+    client_ids: &ClientIds,
+    current: u8,
+    max: u8,
+    position_q: &mut Query<&mut PlayerPosition>,
+) {
+    let mut client_ids = client_ids.0.write().unwrap();
+    let mut racers: Vec<_> = client_ids
+        .iter_mut()
+        .filter_map(|(_, client)| client.lane.map(|lane| (lane, client)))
+        .collect();
+    racers.sort_unstable_by_key(|(lane, _)| *lane);
+
+    for (new_lane, (_, client)) in racers.into_iter().enumerate() {
+        let new_lane = new_lane as u8;
+        client.lane = Some(new_lane);
+        if let Ok(mut position) = position_q.get_mut(client.entity) {
+            position.0.x = distribute_space(max, new_lane);
+        }
+    }
+    debug_assert_eq!(
+        client_ids.values().filter(|c| c.lane.is_some()).count(),
+        current as usize
+    );
+}
+
 fn handle_connect_event(
     trigger: Trigger<ConnectEvent>,
     client_ids: Res<ClientIds>,
     mut commands: Commands,
     mut player_count: ResMut<SpawnedPlayersCount>,
+    game_end_condition: Res<GameEndCondition>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    script: Option<Res<GameScript>>,
 ) {
     if let Netcode(client_id) = trigger.event().client_id {
-        let pos = Vec3::new(
-            distribute_space(player_count.max, player_count.current),
-            9.0,
-            4.0,
-        );
+        if let Some(script) = &script {
+            script.on_player_join(client_id);
+        }
+        let lane = player_count.current;
+        let pos = Vec3::new(distribute_space(player_count.max, lane), 9.0, 4.0);
         info!("client logged in");
-        let entity = commands
-            .spawn(PlayerBundle {
-                position: PlayerPosition(pos, Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, 0.0)),
-                color: PlayerColor(Color::oklab(0.50, -0.03, -0.09)),
-            })
+        let mut entity = commands.spawn(PlayerBundle {
+            position: PlayerPosition(pos, Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, 0.0)),
+            color: PlayerColor(Color::oklab(0.50, -0.03, -0.09)),
+        });
+        entity
             .insert(VelocityShare {
                 linear: Vec3::ZERO,
                 angular: Vec3::ZERO,
@@ -259,10 +467,36 @@ fn handle_connect_event(
                     ..Default::default()
                 },
                 ..Default::default()
-            })
-            .id();
-        player_count.current += 1;
-        client_ids.0.write().unwrap().insert(client_id, entity);
+            });
+
+        // A client connecting after the match already started can't be
+        // slotted into `player_count`/`GameEndCondition` without corrupting
+        // the start/end conditions everyone else is using, so give it a
+        // `Spectator` marker instead of counting it as a racer.
+        let lane = if game_end_condition.has_started {
+            entity.insert(Spectator);
+            broadcast_system_message(&mut connection_manager, "A spectator joined.", false);
+            None
+        } else {
+            player_count.current += 1;
+            broadcast_system_message(
+                &mut connection_manager,
+                format!(
+                    "{} player(s) connected, waiting for {}.",
+                    player_count.current, player_count.max
+                ),
+                false,
+            );
+            Some(lane)
+        };
+
+        client_ids.0.write().unwrap().insert(
+            client_id,
+            ConnectedClient {
+                entity: entity.id(),
+                lane,
+            },
+        );
     }
 }
 
@@ -280,17 +514,30 @@ fn distribute_space(max: u8, i: u8) -> f32 {
     point
 }
 
+/// How long a single accepted auth connection gets to send its request byte
+/// and, if it's asking for a `ConnectToken`, complete the handshake. Without
+/// this, a client that connects and then withholds or trickles in its bytes
+/// would tie up this connection's task forever -- and before each connection
+/// got its own task, that meant freezing QueryInfo/ConnectToken service for
+/// every other client too.
+const AUTH_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn start_netcode_authentication_task(
     game_server_addr: SocketAddr,
     auth_server_addr: SocketAddr,
     protocol_id: u64,
     private_key: Key,
-    client_ids: Arc<RwLock<HashMap<u64, Entity>>>,
+    client_ids: Arc<RwLock<HashMap<u64, ConnectedClient>>>,
+    lobby_state: Arc<RwLock<LobbySnapshot>>,
+    seed: u32,
+    max_game_seconds: u32,
+    max_players: u8,
+    static_secret: StaticSecret,
 ) {
     IoTaskPool::get()
         .spawn(Compat::new(async move {
             info!(
-                "Listening for ConnectToken requests on {}",
+                "Listening for ConnectToken/QueryInfo requests on {}",
                 auth_server_addr
             );
             let listener = tokio::net::TcpListener::bind(auth_server_addr)
@@ -298,33 +545,111 @@ fn start_netcode_authentication_task(
                 .unwrap();
             loop {
                 // received a new connection
-                let (mut stream, _) = listener.accept().await.unwrap();
-
-                // assign a new client_id
-                let client_id = loop {
-                    let client_id = rand::random();
-                    if !client_ids.read().unwrap().contains_key(&client_id) {
-                        break client_id;
-                    }
-                };
-
-                let token =
-                    ConnectToken::build(game_server_addr, protocol_id, client_id, private_key)
-                        .generate()
-                        .expect("Failed to generate token");
-
-                let serialized_token = token.try_into_bytes().expect("Failed to serialize token");
-                trace!(
-                    "Sending token {:?} to client {}. Token len: {}",
-                    serialized_token,
-                    client_id,
-                    serialized_token.len()
-                );
-                stream
-                    .write_all(&serialized_token)
-                    .await
-                    .expect("Failed to send token to client");
+                let (stream, _) = listener.accept().await.unwrap();
+
+                // Handle each connection on its own task so one slow or
+                // unresponsive client can't block QueryInfo/ConnectToken
+                // service for everyone else.
+                let client_ids = client_ids.clone();
+                let lobby_state = lobby_state.clone();
+                let static_secret = static_secret.clone();
+                IoTaskPool::get()
+                    .spawn(Compat::new(async move {
+                        if tokio::time::timeout(
+                            AUTH_CONNECTION_TIMEOUT,
+                            handle_auth_connection(
+                                stream,
+                                game_server_addr,
+                                protocol_id,
+                                private_key,
+                                client_ids,
+                                lobby_state,
+                                seed,
+                                max_game_seconds,
+                                max_players,
+                                static_secret,
+                            ),
+                        )
+                        .await
+                        .is_err()
+                        {
+                            warn!(
+                                "auth connection timed out after {:?}",
+                                AUTH_CONNECTION_TIMEOUT
+                            );
+                        }
+                    }))
+                    .detach();
             }
         }))
         .detach();
 }
+
+/// Services a single accepted auth connection: either a `QueryInfo` lookup,
+/// answered directly, or a `ConnectToken` request, which needs a fresh
+/// client id and an encrypted handshake before the token can be sent.
+async fn handle_auth_connection(
+    mut stream: tokio::net::TcpStream,
+    game_server_addr: SocketAddr,
+    protocol_id: u64,
+    private_key: Key,
+    client_ids: Arc<RwLock<HashMap<u64, ConnectedClient>>>,
+    lobby_state: Arc<RwLock<LobbySnapshot>>,
+    seed: u32,
+    max_game_seconds: u32,
+    max_players: u8,
+    static_secret: StaticSecret,
+) {
+    let mut request_byte = [0u8; 1];
+    if stream.read_exact(&mut request_byte).await.is_err() {
+        return;
+    }
+
+    if AuthRequest::from_byte(request_byte[0]) == Some(AuthRequest::QueryInfo) {
+        let snapshot = *lobby_state.read().unwrap();
+        let info = ServerQueryInfo {
+            seed,
+            max_game_seconds,
+            max_players,
+            current_players: snapshot.current_players,
+            state: snapshot.state,
+        };
+        let _ = stream.write_all(&info.to_bytes()).await;
+        return;
+    }
+
+    // assign a new client_id
+    let client_id = loop {
+        let client_id = rand::random();
+        if !client_ids.read().unwrap().contains_key(&client_id) {
+            break client_id;
+        }
+    };
+
+    let token = ConnectToken::build(game_server_addr, protocol_id, client_id, private_key)
+        .generate()
+        .expect("Failed to generate token");
+
+    let serialized_token = token.try_into_bytes().expect("Failed to serialize token");
+    trace!(
+        "Sending token {:?} to client {}. Token len: {}",
+        serialized_token,
+        client_id,
+        serialized_token.len()
+    );
+
+    // The token embeds access to the game server, so it's worth
+    // encrypting even on a LAN: run the X25519/HKDF handshake to
+    // get a session key, then ship the token under ChaCha20-Poly1305
+    // instead of writing it in cleartext.
+    let session_key = match server_handshake(&mut stream, &static_secret).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("auth handshake with client {} failed: {}", client_id, e);
+            return;
+        }
+    };
+    if let Err(e) = write_encrypted(&mut stream, &session_key, &serialized_token).await {
+        warn!("failed to send encrypted token to client {}: {}", client_id, e);
+    }
+}
@@ -36,9 +36,9 @@ fn movement(
         let client_id = input.from();
         if let Some(input) = input.input() {
             let client_ids = client_ids.0.read().unwrap();
-            if let Some(player_entity) = client_ids.get(&client_id.to_bits()) {
+            if let Some(connected) = client_ids.get(&client_id.to_bits()) {
                 if let Ok((velocity, force, last_touched, last_velocity)) =
-                    position_query.get_mut(*player_entity)
+                    position_query.get_mut(connected.entity)
                 {
                     if time.elapsed_secs() - last_touched.at < 1.0 || last_touched.touching {
                         torque_function(velocity, force, last_velocity, input);
@@ -157,6 +157,14 @@ fn torque_function(
                 velocity.angvel = Vec3::ZERO;
             }
         }
+        Inputs::Analog { steer, throttle } => {
+            let forward_torque = up.cross(lin).normalize();
+            let turn_angle = steer.clamp(-1.0, 1.0) * PI * 0.75;
+            force.torque = Quat::from_rotation_y(-turn_angle)
+                * forward_torque
+                * multiplier
+                * throttle.clamp(-1.0, 1.0);
+        }
         Inputs::None => force.torque = Vec3::ZERO,
         _ => (),
     }
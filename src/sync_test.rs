@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{protocol::PlayerPosition, world::FrameCounter};
+
+#[derive(Resource)]
+pub struct SyncTestConfig {
+    pub enabled: bool,
+    /// Maximum distance, in world units, allowed between a ball's predicted
+    /// and actual position before it's treated as a real desync rather than
+    /// float noise.
+    pub tolerance: f32,
+}
+
+/// How many times `sync_test_system` has caught the Euler prediction
+/// drifting from what Rapier actually produced. One or two, this tick, are
+/// worth a `warn!`; this resource is what lets something watching across
+/// many ticks notice it's becoming a pattern instead of a one-off.
+#[derive(Resource, Default)]
+pub struct DesyncCount(pub u64);
+
+/// A ball's state as of the end of the previous fixed tick, kept so this
+/// tick can predict what it "should" become and compare that prediction
+/// against what Rapier actually produced.
+#[derive(Component, Clone, Copy)]
+struct PreviousTickState {
+    translation: Vec3,
+    linvel: Vec3,
+}
+
+/// This is a reduced stand-in for GGRS's `SyncTestSession`, which owns two
+/// full world copies and re-simulates a frame twice from scratch. We don't
+/// have a second Rapier pipeline to drive, so instead we predict this tick's
+/// state from last tick's saved snapshot via plain Euler integration
+/// (`pos + linvel * dt`) and compare that prediction against what Rapier
+/// actually produced. A real divergence (e.g. a variable timestep sneaking
+/// back in, or a non-deterministic force) shows up as the two disagreeing by
+/// more than `SyncTestConfig::tolerance`; anything Rapier's own forces
+/// (gravity, collision response) legitimately change tick-to-tick is outside
+/// what this check can model, so it's meant to catch gross nondeterminism,
+/// not validate exact physics.
+pub fn sync_test_system(
+    config: Res<SyncTestConfig>,
+    counter: Res<FrameCounter>,
+    time: Res<Time>,
+    mut desync_count: ResMut<DesyncCount>,
+    mut commands: Commands,
+    mut balls: Query<
+        (Entity, &Transform, &Velocity, Option<&mut PreviousTickState>),
+        With<PlayerPosition>,
+    >,
+) {
+    let dt = time.delta_secs();
+
+    for (entity, transform, velocity, previous) in &mut balls {
+        if config.enabled {
+            if let Some(mut previous) = previous {
+                let predicted_translation = previous.translation + previous.linvel * dt;
+                let drift = (predicted_translation - transform.translation).length();
+                if drift > config.tolerance {
+                    desync_count.0 += 1;
+                    warn!(
+                        total = desync_count.0,
+                        "sync-test desync detected at frame {}: predicted ball to be at {predicted_translation:?}, Rapier produced {:?} (drift {drift})",
+                        counter.0, transform.translation
+                    );
+                }
+                previous.translation = transform.translation;
+                previous.linvel = velocity.linvel;
+            } else {
+                commands.entity(entity).insert(PreviousTickState {
+                    translation: transform.translation,
+                    linvel: velocity.linvel,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::{
+    math::DVec3,
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+        render_resource::PrimitiveTopology,
+    },
+};
+use noise::{NoiseFn, Perlin};
+
+use crate::{
+    mc_tables::{CORNER_OFFSETS, EDGE_CORNERS, EDGE_TABLE, TRI_TABLE},
+    track_mesh::{TRACK_WIDTH, compute_area_weighted_normals, weld_mesh},
+};
+
+const ISOLEVEL: f64 = 0.0;
+const CELL_SIZE: f32 = 2.0;
+const NOISE_FREQUENCY: f64 = 0.08;
+/// How far (in local block space) from the track centerline the density
+/// falloff forces air, keeping the track itself clear of the surrounding
+/// terrain regardless of what the Perlin field does there.
+const CLEARANCE_RADIUS: f64 = TRACK_WIDTH as f64 * 1.5;
+
+/// Generates organic terrain (walls, cliffs, caves) around a track block by
+/// marching cubes over a Perlin density field, minus a falloff that keeps the
+/// track itself clear. `min`/`max` bound the grid in the block's local space
+/// (the same space the block's own mesh is generated in, so both share the
+/// segment's `Transform`); `world_offset` is the block's world-space position,
+/// added to each grid point before the noise lookup. The noise is the same
+/// `Perlin` that `generate_bumpy_mesh` uses, but sampled in 3D at
+/// `NOISE_FREQUENCY`, so the terrain does not follow the bumpy surface's bumps.
+pub fn generate_terrain_mesh(min: Vec3, max: Vec3, noise: &Perlin, world_offset: DVec3) -> Mesh {
+    let cells_x = (((max.x - min.x) / CELL_SIZE).ceil() as usize).max(1);
+    let cells_y = (((max.y - min.y) / CELL_SIZE).ceil() as usize).max(1);
+    let cells_z = (((max.z - min.z) / CELL_SIZE).ceil() as usize).max(1);
+    let dim_x = cells_x + 1;
+    let dim_y = cells_y + 1;
+    let point_index = |x: usize, y: usize, z: usize| x + y * dim_x + z * dim_x * dim_y;
+
+    let grid_point = |x: usize, y: usize, z: usize| -> DVec3 {
+        DVec3::new(
+            min.x as f64 + x as f64 * CELL_SIZE as f64,
+            min.y as f64 + y as f64 * CELL_SIZE as f64,
+            min.z as f64 + z as f64 * CELL_SIZE as f64,
+        )
+    };
+
+    let mut field = vec![0.0f64; dim_x * dim_y * (cells_z + 1)];
+    for z in 0..=cells_z {
+        for y in 0..=cells_y {
+            for x in 0..=cells_x {
+                field[point_index(x, y, z)] = density(grid_point(x, y, z), noise, world_offset);
+            }
+        }
+    }
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    for z in 0..cells_z {
+        for y in 0..cells_y {
+            for x in 0..cells_x {
+                let corner_value: [f64; 8] = std::array::from_fn(|c| {
+                    let [ox, oy, oz] = CORNER_OFFSETS[c];
+                    field[point_index(x + ox as usize, y + oy as usize, z + oz as usize)]
+                });
+
+                let mut case_index = 0usize;
+                for (c, &value) in corner_value.iter().enumerate() {
+                    if value < ISOLEVEL {
+                        case_index |= 1 << c;
+                    }
+                }
+
+                let edge_mask = EDGE_TABLE[case_index];
+                if edge_mask == 0 {
+                    continue;
+                }
+
+                let corner_point: [DVec3; 8] = std::array::from_fn(|c| {
+                    let [ox, oy, oz] = CORNER_OFFSETS[c];
+                    grid_point(x + ox as usize, y + oy as usize, z + oz as usize)
+                });
+
+                let mut edge_point = [DVec3::ZERO; 12];
+                for (e, slot) in edge_point.iter_mut().enumerate() {
+                    if edge_mask & (1 << e) != 0 {
+                        let [a, b] = EDGE_CORNERS[e];
+                        *slot = interpolate_edge(
+                            corner_point[a],
+                            corner_value[a],
+                            corner_point[b],
+                            corner_value[b],
+                        );
+                    }
+                }
+
+                for triangle in TRI_TABLE[case_index].chunks_exact(3) {
+                    if triangle[0] < 0 {
+                        break;
+                    }
+                    for &e in triangle {
+                        let p = edge_point[e as usize];
+                        positions.push([p.x as f32, p.y as f32, p.z as f32]);
+                        uvs.push([p.x as f32 * 0.1, p.z as f32 * 0.1]);
+                        indices.push((positions.len() - 1) as u32);
+                    }
+                }
+            }
+        }
+    }
+
+    let normals = compute_area_weighted_normals(&positions, &indices);
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_indices(Indices::U32(indices));
+
+    weld_mesh(&mut mesh);
+    mesh
+}
+
+/// `perlin3(p * frequency)` minus a falloff that goes to its maximum at the
+/// track centerline and fades out past `CLEARANCE_RADIUS`, so the isosurface
+/// never intrudes on the track regardless of what the noise field says there.
+fn density(p: DVec3, noise: &Perlin, world_offset: DVec3) -> f64 {
+    let sample = (p + world_offset) * NOISE_FREQUENCY;
+    let noise_value = noise.get([sample.x, sample.y, sample.z]);
+
+    let track_center = DVec3::new(0.0, TRACK_WIDTH as f64 * 0.15, p.z);
+    let horizontal_distance = (p - track_center).length();
+    let clearance = ((CLEARANCE_RADIUS - horizontal_distance) / CLEARANCE_RADIUS).max(0.0);
+
+    noise_value - clearance * 2.0
+}
+
+fn interpolate_edge(pa: DVec3, da: f64, pb: DVec3, db: f64) -> DVec3 {
+    if (db - da).abs() < 1e-6 {
+        return pa;
+    }
+    let t = ((ISOLEVEL - da) / (db - da)).clamp(0.0, 1.0);
+    pa + (pb - pa) * t
+}
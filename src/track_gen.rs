@@ -32,6 +32,21 @@ pub enum BlockType {
     },
 }
 
+impl BlockType {
+    /// A rough world-space length for this block, used to size bounding
+    /// volumes (e.g. for the marching-cubes terrain surround) rather than for
+    /// anything that needs to be exact.
+    pub fn approx_length(&self) -> f32 {
+        match self {
+            BlockType::Straight { length } => *length,
+            BlockType::Slope { length, .. } => *length,
+            BlockType::Bumpy { length, .. } => *length,
+            BlockType::Turn { angle, radius } => radius * angle.abs(),
+            BlockType::BankedTurn { angle, radius, .. } => radius * angle.abs(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum RoadType {
     Asphalt,
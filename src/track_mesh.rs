@@ -1,6 +1,13 @@
+use std::collections::HashMap;
+
 use bevy::{
+    math::DVec3,
     prelude::*,
-    render::{mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology},
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+        render_resource::PrimitiveTopology,
+    },
 };
 use noise::{NoiseFn, Perlin};
 
@@ -11,7 +18,7 @@ pub const TRACK_WIDTH: f32 = 10.0;
 const SEGMENTS_PER_RADIAN: usize = 10;
 
 // Function to generate a mesh for a single block
-pub fn generate_mesh_for_block(block: BlockType, noise: &Perlin, offset: Vec3) -> Mesh {
+pub fn generate_mesh_for_block(block: BlockType, noise: &Perlin, offset: DVec3) -> Mesh {
     match block {
         BlockType::Straight { length } => generate_straight_mesh(length),
         BlockType::Turn { angle, radius } => generate_turn_mesh(angle, radius),
@@ -77,19 +84,7 @@ fn generate_straight_mesh(length: f32) -> Mesh {
         [1.0, 1.0],
         [0.0, 1.0],
     ];
-    // Normals: all pointing up (Y+)
-    let normals = vec![
-        [0.0, 1.0, 0.0],
-        [0.0, 1.0, 0.0],
-        [0.0, 1.0, 0.0],
-        [0.0, 1.0, 0.0],
-        [1.0, 0.0, 0.0],
-        [1.0, 0.0, 0.0],
-        [-1.0, 0.0, 0.0],
-        [-1.0, 0.0, 0.0],
-    ];
-
-    create_mesh_from_attributes(vertices, indices, uvs, normals)
+    create_mesh_from_attributes(vertices, indices, uvs, true)
 }
 fn generate_bumpy_mesh(
     length: f32,
@@ -98,7 +93,7 @@ fn generate_bumpy_mesh(
     resolution_z: usize,
     perturbations: f32,
     noise: &Perlin,
-    offset: Vec3,
+    offset: DVec3,
 ) -> Mesh {
     // --- Validate Input ---
     // Ensure resolution is at least 2x2 to form a grid
@@ -111,7 +106,6 @@ fn generate_bumpy_mesh(
     let num_indices = num_quads * 6; // 2 triangles per quad, 3 indices per triangle
 
     let mut positions: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
-    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
     let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(num_vertices);
     let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
 
@@ -137,8 +131,8 @@ fn generate_bumpy_mesh(
                     // Use noise function. Note: The `noise` crate often uses f64.
                     // The noise value is typically between -1.0 and 1.0.
                     let noise_val = noise.get([
-                        x_pos as f64 / 6.0 + offset.x as f64,
-                        z_pos as f64 / 6.0 + offset.y as f64,
+                        x_pos as f64 / 6.0 + offset.x,
+                        z_pos as f64 / 6.0 + offset.z,
                     ]);
                     noise_val as f32 * perturbations
                 } else {
@@ -148,8 +142,6 @@ fn generate_bumpy_mesh(
 
             positions.push([x_pos, height, z_pos]);
             uvs.push([u, v]);
-            // Initialize normals pointing up. We'll calculate accurate normals later.
-            normals.push([0.0, 1.0, 0.0]);
         }
     }
     positions.extend_from_slice(&[
@@ -158,12 +150,6 @@ fn generate_bumpy_mesh(
         [half_width, 3.0, length],  // Top right
         [-half_width, 3.0, length], // Top left
     ]);
-    normals.extend_from_slice(&[
-        [0.0, 0.0, 0.0],
-        [0.0, 0.0, 0.0],
-        [0.0, 0.0, 0.0],
-        [0.0, 0.0, 0.0],
-    ]);
 
     // --- Generate Indices ---
     // Iterate through each quad of the grid
@@ -206,18 +192,11 @@ fn generate_bumpy_mesh(
         ((resolution_x - 0) * (resolution_z - 0)) as u32 - 1,
         positions.len() as u32 - 3, // bottom right
     ]);
-    // --- Calculate Accurate Normals ---
-    // Reset normals to zero before accumulating face normals
-    for n in normals.iter_mut() {
-        *n = [0.0, 0.0, 0.0];
-    }
-    normals.push([0.0, 0.0, 0.0]);
-    normals.push([0.0, 0.0, 0.0]);
-    normals.push([0.0, 0.0, 0.0]);
-    normals.push([0.0, 0.0, 0.0]);
-
     uvs.extend_from_slice(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
 
+    // --- Calculate Accurate Normals ---
+    let normals = compute_area_weighted_normals(&positions, &indices);
+
     // --- Create Bevy Mesh ---
     let mut mesh = Mesh::new(
         PrimitiveTopology::TriangleList,
@@ -227,7 +206,10 @@ fn generate_bumpy_mesh(
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
     mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
     mesh.insert_indices(Indices::U32(indices));
-    mesh.compute_smooth_normals();
+    weld_mesh(&mut mesh);
+
+    let tangents = compute_tangents(&mesh);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
 
     mesh
 }
@@ -240,7 +222,6 @@ fn generate_turn_mesh(angle: f32, radius: f32) -> Mesh {
 
     let mut vertices = Vec::with_capacity(((segments + 1) * 2) as usize);
     let mut uvs = Vec::with_capacity(((segments + 1) * 2) as usize);
-    let mut normals = Vec::with_capacity(((segments + 1) * 2) as usize);
     let mut indices: Vec<u32> = Vec::with_capacity((segments * 6) as usize);
 
     // Generate vertices along the arc
@@ -266,10 +247,6 @@ fn generate_turn_mesh(angle: f32, radius: f32) -> Mesh {
         uvs.push([i as f32 / segments as f32, 0.0]);
         uvs.push([i as f32 / segments as f32, 1.0]);
         uvs.push([i as f32 / segments as f32, 1.0]);
-        normals.push([0.0, 1.0, 0.0]);
-        normals.push([1.0, 0.0, 0.0]);
-        normals.push([0.0, 1.0, 0.0]);
-        normals.push([-1.0, 0.0, 0.0]);
 
         // Add indices for the quad (two triangles)
         if i < segments {
@@ -300,7 +277,7 @@ fn generate_turn_mesh(angle: f32, radius: f32) -> Mesh {
         }
     }
 
-    create_mesh_from_attributes(vertices, indices, uvs, normals)
+    create_mesh_from_attributes(vertices, indices, uvs, true)
 }
 
 // Turn mesh - an arc segment with specified radius and angle
@@ -311,7 +288,6 @@ fn generate_banked_turn_mesh(angle: f32, radius: f32, bank_height: f32) -> Mesh
 
     let mut vertices = Vec::with_capacity(((segments + 1) * 2) as usize);
     let mut uvs = Vec::with_capacity(((segments + 1) * 2) as usize);
-    let mut normals = Vec::with_capacity(((segments + 1) * 2) as usize);
     let mut indices: Vec<u32> = Vec::with_capacity((segments * 6) as usize);
 
     // Generate vertices along the arc
@@ -338,10 +314,6 @@ fn generate_banked_turn_mesh(angle: f32, radius: f32, bank_height: f32) -> Mesh
         uvs.push([i as f32 / segments as f32, 0.0]);
         uvs.push([i as f32 / segments as f32, 1.0]);
         uvs.push([i as f32 / segments as f32, 1.0]);
-        normals.push([0.0, 1.0, 0.0]);
-        normals.push([1.0, 0.0, 0.0]);
-        normals.push([0.0, 1.0, 0.0]);
-        normals.push([-1.0, 0.0, 0.0]);
 
         // Add indices for the quad (two triangles)
         if i < segments {
@@ -372,7 +344,7 @@ fn generate_banked_turn_mesh(angle: f32, radius: f32, bank_height: f32) -> Mesh
         }
     }
 
-    create_mesh_from_attributes(vertices, indices, uvs, normals)
+    create_mesh_from_attributes(vertices, indices, uvs, true)
 }
 
 fn sigmoid_peak(i: usize, max: usize) -> f32 {
@@ -449,29 +421,7 @@ fn generate_slope_mesh(length: f32, height_change: f32) -> Mesh {
         [0.0, 1.0],
     ];
 
-    // Calculate normalized normal for the slope
-    let dx = length;
-    let dy = height_change;
-    let normal_length = (dx * dx + dy * dy).sqrt();
-
-    let normal = [
-        -dy / normal_length, // X component (depends on slope)
-        dx / normal_length,  // Y component (depends on slope)
-        0.0,                 // Z component (no tilt in Z direction)
-    ];
-
-    let normals = vec![
-        normal,
-        normal,
-        normal,
-        normal,
-        [1.0, 0.0, 0.0],
-        [1.0, 0.0, 0.0],
-        [-1.0, 0.0, 0.0],
-        [-1.0, 0.0, 0.0],
-    ];
-
-    create_mesh_from_attributes(vertices, indices, uvs, normals)
+    create_mesh_from_attributes(vertices, indices, uvs, true)
 }
 
 // Helper function to create a mesh from attributes
@@ -479,16 +429,206 @@ fn create_mesh_from_attributes(
     positions: Vec<[f32; 3]>,
     indices: Vec<u32>,
     uvs: Vec<[f32; 2]>,
-    normals: Vec<[f32; 3]>,
+    weld: bool,
 ) -> Mesh {
-    Mesh::new(
+    let normals = compute_area_weighted_normals(&positions, &indices);
+    let mut mesh = Mesh::new(
         PrimitiveTopology::TriangleList,
         RenderAssetUsages::default(),
     )
     .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
     .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
     .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-    .with_inserted_indices(Indices::U32(indices))
+    .with_inserted_indices(Indices::U32(indices));
+
+    if weld {
+        weld_mesh(&mut mesh);
+    }
+
+    let tangents = compute_tangents(&mesh);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
+
+    mesh
+}
+
+/// Deduplicates vertices that share the same position, UV, and normal (within
+/// `WELD_EPSILON`) and rewrites the index buffer to point at the shared copy.
+/// Block generators duplicate every corner vertex per quad face, which bloats
+/// memory and breaks smooth shading across quad seams; welding collapses
+/// those duplicates back into one vertex each.
+pub(crate) fn weld_mesh(mesh: &mut Mesh) {
+    const WELD_EPSILON: f32 = 1e-4;
+    let quantize = |v: f32| (v / WELD_EPSILON).round() as i64;
+
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(p)) => p.clone(),
+        _ => return,
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uv)) => uv.clone(),
+        _ => vec![[0.0, 0.0]; positions.len()],
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(n)) => n.clone(),
+        _ => vec![[0.0, 1.0, 0.0]; positions.len()],
+    };
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U32(i)) => i.clone(),
+        Some(Indices::U16(i)) => i.iter().map(|&i| i as u32).collect(),
+        None => return,
+    };
+
+    let mut unique_positions = Vec::new();
+    let mut unique_uvs = Vec::new();
+    let mut unique_normals = Vec::new();
+    let mut remap: HashMap<(i64, i64, i64, i64, i64, i64, i64, i64), u32> = HashMap::new();
+    let mut old_to_new = Vec::with_capacity(positions.len());
+
+    for i in 0..positions.len() {
+        let p = positions[i];
+        let uv = uvs[i];
+        let n = normals[i];
+        let key = (
+            quantize(p[0]),
+            quantize(p[1]),
+            quantize(p[2]),
+            quantize(uv[0]),
+            quantize(uv[1]),
+            quantize(n[0]),
+            quantize(n[1]),
+            quantize(n[2]),
+        );
+
+        let new_index = *remap.entry(key).or_insert_with(|| {
+            let new_index = unique_positions.len() as u32;
+            unique_positions.push(p);
+            unique_uvs.push(uv);
+            unique_normals.push(n);
+            new_index
+        });
+        old_to_new.push(new_index);
+    }
+
+    let welded_indices: Vec<u32> = indices
+        .into_iter()
+        .map(|i| old_to_new[i as usize])
+        .collect();
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, unique_positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, unique_uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, unique_normals);
+    mesh.insert_indices(Indices::U32(welded_indices));
+}
+
+/// Recomputes per-vertex normals from geometry alone: zeroes every normal,
+/// then for each triangle adds the un-normalized cross product of two of its
+/// edges to each of the triangle's three vertices (an un-normalized cross
+/// product is already proportional to the triangle's area, so larger
+/// triangles naturally outweigh smaller ones sharing the same vertex) before
+/// normalizing each accumulated vertex normal. Shared by every block
+/// generator so banked turns and slopes shade correctly instead of relying on
+/// a hard-coded `[0, 1, 0]` floor normal.
+pub(crate) fn compute_area_weighted_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let p0 = Vec3::from_array(positions[triangle[0] as usize]);
+        let p1 = Vec3::from_array(positions[triangle[1] as usize]);
+        let p2 = Vec3::from_array(positions[triangle[2] as usize]);
+        let face_normal = (p1 - p0).cross(p2 - p0);
+
+        for &i in triangle {
+            normals[i as usize] += face_normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|n| n.try_normalize().unwrap_or(Vec3::Y).to_array())
+        .collect()
+}
+
+/// Fills `Mesh::ATTRIBUTE_TANGENT` (Float32x4, xyz + handedness sign) for every
+/// vertex so normal-mapped materials render correctly on curved/banked blocks.
+/// Per triangle, accumulates the UV-space tangent/bitangent onto each of its
+/// three vertices, then per vertex Gram-Schmidt-orthonormalizes the tangent
+/// against the (already smoothed) normal and derives the handedness sign from
+/// the accumulated bitangent. Triangles with near-collinear UVs contribute
+/// nothing to the accumulation; vertices that end up with a degenerate
+/// tangent fall back to an arbitrary vector orthogonal to the normal.
+fn compute_tangents(mesh: &Mesh) -> Vec<[f32; 4]> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return Vec::new();
+    };
+    let fallback = vec![[1.0, 0.0, 0.0, 1.0]; positions.len()];
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        return fallback;
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return fallback;
+    };
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U32(indices)) => indices.clone(),
+        Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+        None => return fallback,
+    };
+
+    let mut tangent_accum = vec![Vec3::ZERO; positions.len()];
+    let mut bitangent_accum = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let (p0, p1, p2) = (
+            Vec3::from(positions[i0]),
+            Vec3::from(positions[i1]),
+            Vec3::from(positions[i2]),
+        );
+        let (uv0, uv1, uv2) = (
+            Vec2::from(uvs[i0]),
+            Vec2::from(uvs[i1]),
+            Vec2::from(uvs[i2]),
+        );
+
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+        let duv1 = uv1 - uv0;
+        let duv2 = uv2 - uv0;
+
+        let det = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() < 1e-8 {
+            // Degenerate (collinear) UVs: don't let this triangle skew the
+            // accumulated tangent, the per-vertex fallback handles it instead.
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (e1 * duv2.y - e2 * duv1.y) * r;
+        let bitangent = (e2 * duv1.x - e1 * duv2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangent_accum[i] += tangent;
+            bitangent_accum[i] += bitangent;
+        }
+    }
+
+    (0..positions.len())
+        .map(|i| {
+            let normal = Vec3::from(normals[i]);
+            let projected = tangent_accum[i] - normal * normal.dot(tangent_accum[i]);
+            let tangent = projected.try_normalize().unwrap_or_else(|| {
+                let arbitrary = if normal.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+                (arbitrary - normal * normal.dot(arbitrary)).normalize()
+            });
+            let handedness = if normal.cross(tangent).dot(bitangent_accum[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [tangent.x, tangent.y, tangent.z, handedness]
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    protocol::PlayerPosition,
+    track_gen::RoadType,
+    world::{GravityModifier, LastTouched, SegmentRoadTypes},
+};
+
+/// How fast a ball must be moving on `RoadType::Ice` before it throws up a
+/// spray of particles, so a ball that's nearly stopped on ice doesn't look
+/// like it's constantly fizzing.
+const ICE_SPRAY_SPEED_THRESHOLD: f32 = 4.0;
+/// How often a ball on ice and above the speed threshold spawns a new spray
+/// particle.
+const ICE_SPRAY_INTERVAL_SECS: f32 = 0.08;
+/// How long a single particle survives before despawning.
+const PARTICLE_LIFETIME_SECS: f32 = 0.4;
+
+pub struct VfxPlugin;
+
+impl Plugin for VfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                attach_vfx_state,
+                ice_spray_system,
+                boost_glow_system,
+                update_particles,
+            ),
+        );
+    }
+}
+
+/// A single spawned particle: moves along `velocity` and shrinks away over
+/// `lifetime`, then despawns.
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    lifetime: Timer,
+}
+
+/// Marks the glow child spawned under a boosted ball, so `boost_glow_system`
+/// can find and despawn it again once the boost ends.
+#[derive(Component)]
+struct BoostGlow;
+
+/// Per-ball timer gating how often `ice_spray_system` spawns a new particle,
+/// and the edge-detection state `boost_glow_system` uses to tell "just
+/// started boosting" from "still boosting" from "just stopped".
+#[derive(Component)]
+struct VfxState {
+    ice_spray_timer: Timer,
+    boosting: bool,
+    glow: Option<Entity>,
+}
+
+impl Default for VfxState {
+    fn default() -> Self {
+        Self {
+            ice_spray_timer: Timer::from_seconds(ICE_SPRAY_INTERVAL_SECS, TimerMode::Repeating),
+            boosting: false,
+            glow: None,
+        }
+    }
+}
+
+fn attach_vfx_state(
+    mut commands: Commands,
+    query: Query<Entity, (With<PlayerPosition>, With<GravityModifier>, Without<VfxState>)>,
+) {
+    for entity in &query {
+        commands.entity(entity).insert(VfxState::default());
+    }
+}
+
+/// Throws up a trail of particles while a ball is moving fast over ice,
+/// giving the player a visual cue for the grip loss `Friction` already
+/// applies under the hood.
+fn ice_spray_system(
+    time: Res<Time>,
+    road_types: Option<Res<SegmentRoadTypes>>,
+    mut balls: Query<(&Transform, &Velocity, &LastTouched, &mut VfxState)>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(road_types) = road_types else {
+        return;
+    };
+    for (transform, velocity, last_touched, mut state) in &mut balls {
+        let on_ice = matches!(road_types.0.get(last_touched.road_id), Some(RoadType::Ice));
+        let fast_enough = velocity.linvel.length() > ICE_SPRAY_SPEED_THRESHOLD;
+
+        if !on_ice || !fast_enough || !last_touched.touching {
+            continue;
+        }
+
+        state.ice_spray_timer.tick(time.delta());
+        if !state.ice_spray_timer.just_finished() {
+            continue;
+        }
+
+        let spray_direction = (-velocity.linvel.normalize_or_zero() + Vec3::Y * 0.5).normalize_or_zero();
+        spawn_particle(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            transform.translation - Vec3::Y * 0.4,
+            spray_direction * 2.0,
+            Color::srgba(0.8, 0.9, 1.0, 0.8),
+        );
+    }
+}
+
+/// Spawns a burst when a ball's `GravityModifier` flips from inactive to
+/// active (i.e. it just touched a `GravityChange` booster), and keeps a glow
+/// child attached to the ball for as long as `GravityModifier.remaining`
+/// hasn't finished, despawning it the moment the modifier wears off.
+fn boost_glow_system(
+    mut commands: Commands,
+    mut balls: Query<(Entity, &GravityModifier, &Transform, &mut VfxState)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, modifier, transform, mut state) in &mut balls {
+        let boosting = !modifier.remaining.finished();
+
+        if boosting && !state.boosting {
+            for i in 0..6 {
+                let angle = i as f32 / 6.0 * std::f32::consts::TAU;
+                let direction = Vec3::new(angle.cos(), 0.5, angle.sin());
+                spawn_particle(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    transform.translation,
+                    direction * 3.0,
+                    Color::srgba(1.0, 0.6, 0.1, 0.9),
+                );
+            }
+
+            let glow = commands
+                .spawn((
+                    Mesh3d(meshes.add(Sphere::new(0.7))),
+                    MeshMaterial3d(materials.add(StandardMaterial {
+                        base_color: Color::srgba(1.0, 0.6, 0.1, 0.35),
+                        emissive: LinearRgba::rgb(3.0, 1.2, 0.0),
+                        alpha_mode: AlphaMode::Blend,
+                        ..default()
+                    })),
+                    Transform::IDENTITY,
+                    BoostGlow,
+                ))
+                .id();
+            commands.entity(entity).add_child(glow);
+            state.glow = Some(glow);
+        } else if !boosting && state.boosting {
+            if let Some(glow) = state.glow.take() {
+                commands.entity(glow).despawn();
+            }
+        }
+
+        state.boosting = boosting;
+    }
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    position: Vec3,
+    velocity: Vec3,
+    color: Color,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(0.1))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: color,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        })),
+        Transform::from_translation(position),
+        Particle {
+            velocity,
+            lifetime: Timer::from_seconds(PARTICLE_LIFETIME_SECS, TimerMode::Once),
+        },
+    ));
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Transform, &mut Particle)>,
+) {
+    for (entity, mut transform, mut particle) in &mut particles {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation += particle.velocity * time.delta_secs();
+        let remaining = 1.0 - particle.lifetime.fraction();
+        transform.scale = Vec3::splat(remaining.max(0.05));
+    }
+}
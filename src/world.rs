@@ -1,15 +1,45 @@
+use std::path::PathBuf;
+
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
 use crate::{
+    mesh_export,
     player::GameEndCondition,
     protocol::PlayerPosition,
+    terrain,
     track_gen::{BallModifier, RoadType, Track, TrackSegment},
     track_mesh::{TRACK_WIDTH, generate_mesh_for_block},
 };
 
+/// Bounding volume (in the block's own local space) that the marching-cubes
+/// terrain surround is generated within around each track block.
+const TERRAIN_HALF_WIDTH: f32 = TRACK_WIDTH * 2.5;
+const TERRAIN_DEPTH: f32 = 10.0;
+const TERRAIN_HEIGHT: f32 = 16.0;
+
 pub struct WorldPlugin {
     pub physics: bool,
+    /// If set, each generated track block's mesh is also written out as
+    /// `block_<i>.stl`/`.obj` in this directory, for 3D printing or importing
+    /// into external tools like Blender.
+    pub export_track_dir: Option<PathBuf>,
+    /// World-space Y below which a ball is considered to have fallen off the
+    /// track and is respawned at the checkpoint for its last-touched segment.
+    pub respawn_fall_y: f32,
+    /// How long `LastTouched.touching` may stay false before the ball is
+    /// respawned anyway, for balls stuck off-track rather than falling
+    /// (wedged in a wall pocket, etc).
+    pub respawn_stuck_secs: f32,
+    /// Time penalty added to a ball's eventual `Finish::Time` for every
+    /// respawn it triggers.
+    pub respawn_penalty_secs: f32,
+    /// Fixed physics tick rate, in hertz. Rapier is stepped from `FixedUpdate`
+    /// at this rate rather than from `Update`'s variable frame delta, so the
+    /// same sequence of inputs produces the same sequence of physics states
+    /// on every machine. Should match `config::shared_config`'s tick rate,
+    /// since the two are meant to stay in lockstep.
+    pub fixed_hz: f64,
 }
 
 #[derive(Component)]
@@ -27,6 +57,13 @@ pub struct Finished {
     pub at: f32,
 }
 
+/// Where a ball was at the start of the current simulation tick, so
+/// `collision_system` can sweep-test the segment it just moved through
+/// instead of relying solely on `CollisionEvent`, which CCD + sensors don't
+/// reliably raise for a fast-moving ball.
+#[derive(Component, Default)]
+pub struct PreviousPosition(pub Vec3);
+
 #[derive(Component)]
 pub struct ModifierTrigger(BallModifier);
 
@@ -37,6 +74,41 @@ pub struct GravityModifier {
     pub current: f32,
 }
 
+/// Per-segment respawn transform, positioned at the center/top of each
+/// `TrackSegment` at spawn time. Indexed by `LastTouched.road_id`.
+#[derive(Resource)]
+struct RespawnPoints(Vec<Transform>);
+
+/// Each segment's `RoadType`, indexed by `LastTouched.road_id`, so systems
+/// like `vfx`'s ice-spray can tell what surface a ball is currently on
+/// without re-walking the track.
+#[derive(Resource)]
+pub struct SegmentRoadTypes(pub Vec<RoadType>);
+
+/// Tunables for `respawn_system`, taken straight from `WorldPlugin`'s fields
+/// so tracks with big jumps can tune them without touching code.
+#[derive(Resource)]
+struct RespawnConfig {
+    fall_y: f32,
+    stuck_secs: f32,
+    penalty_secs: f32,
+}
+
+/// Total time penalty a ball has accrued from respawning, folded into its
+/// eventual `Finish::Time` by `game_end_system`.
+#[derive(Component, Default)]
+pub struct RespawnPenalty(pub f32);
+
+/// Counts fixed physics steps rather than relying on wall-clock `Time`, so a
+/// desync can be pinned to "frame N" instead of a fuzzy timestamp.
+/// `sync_test::sync_test_system` tags its desync warnings with this value.
+#[derive(Resource, Default)]
+pub struct FrameCounter(pub u64);
+
+fn tick_frame_counter(mut counter: ResMut<FrameCounter>) {
+    counter.0 += 1;
+}
+
 #[derive(Resource)]
 struct Physics(bool);
 
@@ -49,16 +121,44 @@ pub struct LowGpu(pub bool);
 #[derive(Component)]
 struct GoalLine;
 
+/// Marks every entity `spawn_world` creates, so `regenerate_world_on_seed_change`
+/// can despawn the old track before generating a new one from an updated `Seed`.
+#[derive(Component)]
+struct WorldGeometry;
+
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, spawn_world);
+        app.add_systems(Update, regenerate_world_on_seed_change);
         app.insert_resource(Physics(self.physics));
+        app.insert_resource(ExportTrackDir(self.export_track_dir.clone()));
+        app.insert_resource(RespawnConfig {
+            fall_y: self.respawn_fall_y,
+            stuck_secs: self.respawn_stuck_secs,
+            penalty_secs: self.respawn_penalty_secs,
+        });
+        app.insert_resource(FrameCounter::default());
         if self.physics {
-            app.add_systems(Update, (collision_system, apply_gravity_modification));
+            app.insert_resource(Time::<Fixed>::from_seconds(1.0 / self.fixed_hz));
+            app.add_systems(
+                FixedUpdate,
+                (tick_frame_counter, track_previous_position, collision_system).chain(),
+            );
+            app.add_systems(
+                Update,
+                (
+                    attach_previous_position,
+                    apply_gravity_modification,
+                    respawn_system,
+                ),
+            );
         }
     }
 }
 
+#[derive(Resource)]
+struct ExportTrackDir(Option<PathBuf>);
+
 fn spawn_world(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -67,7 +167,13 @@ fn spawn_world(
     asset_server: Res<AssetServer>,
     seed: Res<Seed>,
     low_gpu: Res<LowGpu>,
+    export_track_dir: Res<ExportTrackDir>,
 ) {
+    if let Some(dir) = &export_track_dir.0 {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            error!("failed to create --export-track-dir {dir:?}: {e}");
+        }
+    }
     commands.spawn((
         DirectionalLight {
             illuminance: 10_000.0, // Adjust the brightness as needed
@@ -82,8 +188,34 @@ fn spawn_world(
     ));
     let track = Track::generate(seed.0, 30.0);
     // let track = Track::debug_straight();
+    let respawn_points = track
+        .segments
+        .iter()
+        .map(|segment| {
+            Transform::from_translation(segment.transform.position + Vec3::Y * 1.0)
+                .with_rotation(segment.transform.rotation)
+        })
+        .collect();
+    commands.insert_resource(RespawnPoints(respawn_points));
+    commands.insert_resource(SegmentRoadTypes(
+        track.segments.iter().map(|s| s.road_type.clone()).collect(),
+    ));
     for (i, segment) in track.segments.iter().enumerate() {
-        let m = generate_mesh_for_block(segment.block_type.clone());
+        let m = generate_mesh_for_block(
+            segment.block_type.clone(),
+            &track.noise,
+            segment.transform.position.as_dvec3(),
+        );
+
+        if let Some(dir) = &export_track_dir.0 {
+            if let Err(e) = mesh_export::write_stl(&m, &dir.join(format!("block_{i}.stl"))) {
+                error!("failed to export block_{i}.stl: {e}");
+            }
+            if let Err(e) = mesh_export::write_obj(&m, &dir.join(format!("block_{i}.obj"))) {
+                error!("failed to export block_{i}.obj: {e}");
+            }
+        }
+
         let collider =
             Collider::from_bevy_mesh(&m, &ComputedColliderShape::TriMesh(TriMeshFlags::all()))
                 .unwrap();
@@ -94,6 +226,7 @@ fn spawn_world(
                 .with_translation(segment.transform.position)
                 .with_rotation(segment.transform.rotation),
             TrackSegmentId(i),
+            WorldGeometry,
         ));
 
         if physics.0 {
@@ -122,6 +255,40 @@ fn spawn_world(
                 physics.0,
             );
         }
+
+        let terrain_mesh = terrain::generate_terrain_mesh(
+            Vec3::new(-TERRAIN_HALF_WIDTH, -TERRAIN_DEPTH, 0.0),
+            Vec3::new(
+                TERRAIN_HALF_WIDTH,
+                TERRAIN_HEIGHT,
+                segment.block_type.approx_length(),
+            ),
+            &track.noise,
+            segment.transform.position.as_dvec3(),
+        );
+        let terrain_collider = if physics.0 {
+            Collider::from_bevy_mesh(
+                &terrain_mesh,
+                &ComputedColliderShape::TriMesh(TriMeshFlags::all()),
+            )
+        } else {
+            None
+        };
+        let mut terrain_entity = commands.spawn((
+            Mesh3d(meshes.add(terrain_mesh)),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::oklab(0.3, -0.01, 0.02),
+                perceptual_roughness: 0.95,
+                ..default()
+            })),
+            Transform::IDENTITY
+                .with_translation(segment.transform.position)
+                .with_rotation(segment.transform.rotation),
+            WorldGeometry,
+        ));
+        if let Some(collider) = terrain_collider {
+            terrain_entity.insert((collider, ActiveEvents::COLLISION_EVENTS));
+        }
     }
     let mut goal_line = commands.spawn((
         Transform::from_translation(track.segments.last().unwrap().transform.position)
@@ -134,6 +301,7 @@ fn spawn_world(
         })),
         Mesh3d(meshes.add(Cuboid::new(TRACK_WIDTH, 10.0, 10.0))),
         GoalLine,
+        WorldGeometry,
     ));
 
     if physics.0 {
@@ -145,6 +313,49 @@ fn spawn_world(
     }
 }
 
+/// Despawns and regenerates the whole track whenever `Seed` changes after
+/// startup, e.g. from a gRPC `Reset` RPC. Without this, changing `Seed` only
+/// ever affected the next `Startup`-only `spawn_world` call, which never
+/// comes again, so a reset client kept racing the old track under a new seed
+/// it never actually saw.
+fn regenerate_world_on_seed_change(
+    mut commands: Commands,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<StandardMaterial>>,
+    physics: Res<Physics>,
+    asset_server: Res<AssetServer>,
+    seed: Res<Seed>,
+    low_gpu: Res<LowGpu>,
+    export_track_dir: Res<ExportTrackDir>,
+    geometry_q: Query<Entity, With<WorldGeometry>>,
+    mut last_seed: Local<Option<u32>>,
+) {
+    if !seed.is_changed() || *last_seed == Some(seed.0) {
+        *last_seed = Some(seed.0);
+        return;
+    }
+    let is_first_run = last_seed.is_none();
+    *last_seed = Some(seed.0);
+    if is_first_run {
+        // `spawn_world` already generated the initial track for this seed.
+        return;
+    }
+
+    for entity in &geometry_q {
+        commands.entity(entity).despawn();
+    }
+    spawn_world(
+        commands,
+        meshes,
+        materials,
+        physics,
+        asset_server,
+        seed,
+        low_gpu,
+        export_track_dir,
+    );
+}
+
 fn spawn_gravity_booster_marker(
     commands: &mut Commands,
     meshes: &mut Assets<Mesh>,
@@ -176,6 +387,7 @@ fn spawn_gravity_booster_marker(
             Collider::cuboid(0.5, 0.5, 0.5),
             Sensor,
             ActiveEvents::COLLISION_EVENTS,
+            WorldGeometry,
         ));
     } else {
         commands.spawn((
@@ -183,6 +395,7 @@ fn spawn_gravity_booster_marker(
             MeshMaterial3d(yellow_material),
             Transform::IDENTITY.with_translation(position),
             ModifierTrigger(modifier),
+            WorldGeometry,
         ));
     }
 }
@@ -239,6 +452,28 @@ fn material_for_segment(
     }
 }
 
+/// Gives a player entity a `PreviousPosition` the first time it has both a
+/// `Transform` and a `PlayerPosition`, i.e. as soon as it's actually
+/// simulated, whichever of `attach_player_model_server`/`rollback`'s
+/// `attach_rollback_ball` spawned it.
+fn attach_previous_position(
+    mut commands: Commands,
+    query: Query<Entity, (With<PlayerPosition>, With<Transform>, Without<PreviousPosition>)>,
+) {
+    for entity in &query {
+        commands.entity(entity).insert(PreviousPosition::default());
+    }
+}
+
+/// Snapshots each player's position once per tick, before this tick's
+/// physics step moves it, so `collision_system` can sweep-test the segment
+/// it travelled through this frame.
+fn track_previous_position(mut players: Query<(&mut PreviousPosition, &Transform)>) {
+    for (mut previous, transform) in &mut players {
+        previous.0 = transform.translation;
+    }
+}
+
 fn apply_gravity_modification(
     mut query: Query<(&mut GravityScale, &mut GravityModifier)>,
     time: Res<Time>,
@@ -253,6 +488,45 @@ fn apply_gravity_modification(
     }
 }
 
+/// Teleports a ball back to the checkpoint for the segment it last touched
+/// when it either falls below `RespawnConfig::fall_y` or has gone too long
+/// without touching any track segment (stuck off to the side somewhere).
+/// Each respawn adds `RespawnConfig::penalty_secs` to the ball's
+/// `RespawnPenalty`, which `game_end_system` folds into its `Finish::Time`.
+fn respawn_system(
+    mut players: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut LastTouched,
+            &mut RespawnPenalty,
+        ),
+        (With<PlayerPosition>, Without<Finished>),
+    >,
+    respawn_points: Res<RespawnPoints>,
+    config: Res<RespawnConfig>,
+    time: Res<Time>,
+) {
+    for (mut transform, mut velocity, mut last_touched, mut penalty) in &mut players {
+        let fallen = transform.translation.y < config.fall_y;
+        let stuck = !last_touched.touching
+            && (time.elapsed_secs() - last_touched.at) > config.stuck_secs;
+        if !fallen && !stuck {
+            continue;
+        }
+
+        let Some(respawn) = respawn_points.0.get(last_touched.road_id) else {
+            continue;
+        };
+        *transform = *respawn;
+        velocity.linvel = Vec3::ZERO;
+        velocity.angvel = Vec3::ZERO;
+        last_touched.at = time.elapsed_secs();
+        last_touched.touching = false;
+        penalty.0 += config.penalty_secs;
+    }
+}
+
 fn collision_system(
     mut collision_events: EventReader<CollisionEvent>,
     mut players: Query<(&mut LastTouched, &mut GravityModifier), With<PlayerPosition>>,
@@ -263,6 +537,12 @@ fn collision_system(
     time: Res<Time>,
     mut commands: Commands,
     mut end_conditon: ResMut<GameEndCondition>,
+    goal_transform_query: Query<&Transform, With<GoalLine>>,
+    swept_players: Query<
+        (Entity, &PreviousPosition, &Transform),
+        (With<PlayerPosition>, Without<Finished>),
+    >,
+    rapier_context: Query<&RapierContext>,
 ) {
     for collision_event in collision_events.read() {
         match collision_event {
@@ -314,6 +594,59 @@ fn collision_system(
             }
         }
     }
+
+    // `CollisionEvent`s are occasionally missed by a fast, CCD-enabled ball
+    // against a `Sensor`, so supplement them with swept tests over the
+    // segment each player travelled this tick.
+    let Ok(goal_transform) = goal_transform_query.get_single() else {
+        return;
+    };
+    let Ok(context) = rapier_context.get_single() else {
+        return;
+    };
+    for (entity, previous, transform) in &swept_players {
+        let from = previous.0;
+        let to = transform.translation;
+        let motion = to - from;
+        let Some(direction) = motion.try_normalize() else {
+            continue;
+        };
+
+        let normal = goal_transform.rotation * Vec3::Z;
+        let d_from = (from - goal_transform.translation).dot(normal);
+        let d_to = (to - goal_transform.translation).dot(normal);
+        if d_from * d_to < 0.0 {
+            let t = d_from / (d_from - d_to);
+            let crossing = from + motion * t;
+            let offset = crossing - goal_transform.translation;
+            let lateral = offset - normal * offset.dot(normal);
+            if lateral.length() <= TRACK_WIDTH / 2.0 {
+                commands.entity(entity).insert(Finished {
+                    at: time.elapsed_secs(),
+                });
+                end_conditon.players_finished += 1;
+                continue;
+            }
+        }
+
+        if let Some((hit_entity, _toi)) = context.cast_ray(
+            from,
+            direction,
+            motion.length(),
+            true,
+            QueryFilter::default().exclude_collider(entity),
+        ) {
+            if let Ok(track_segment_id) = track_segments.get(hit_entity) {
+                if let Ok((mut last_touched, _)) = players.get_mut(entity) {
+                    if track_segment_id.0 > last_touched.road_id {
+                        last_touched.road_id = track_segment_id.0;
+                    }
+                    last_touched.at = time.elapsed_secs();
+                    last_touched.touching = true;
+                }
+            }
+        }
+    }
 }
 
 fn process_potential_collision(